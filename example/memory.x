@@ -1,7 +1,8 @@
 MEMORY
 {
   L2_LIM : ORIGIN = 0x08000000, LENGTH = 1M
-  RAM : ORIGIN = 0x80200000, LENGTH = 16K
+  /* Data and bss are placed at the start, and the heap (32 pages) at 0x80210000. */
+  RAM : ORIGIN = 0x80200000, LENGTH = 192K
   FLASH : ORIGIN = 0x80000000, LENGTH = 16M
 }
 
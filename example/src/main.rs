@@ -12,10 +12,10 @@ use wild_screen_alloc::WildScreenAlloc;
 
 const UART_ADDR: *mut u32 = 0x1001_0000 as *mut u32;
 const HEAP_ADDR: usize = 0x8021_0000;
-const HEAP_SIZE: usize = 8 * 4096;
+const HEAP_SIZE: usize = 32 * 4096;
 
 #[global_allocator]
-static mut ALLOCATOR: WildScreenAlloc = WildScreenAlloc::empty();
+static ALLOCATOR: WildScreenAlloc = WildScreenAlloc::empty();
 
 unsafe fn uart_print(format: &str) {
     for c in format.chars() {
@@ -0,0 +1,76 @@
+//! Alignment arithmetic.
+//! Every `align` must be a power of two, otherwise these functions panic on debug build.
+
+/// Round `addr` up to a multiple of `align`.
+/// Return `None` if the result overflows `usize`.
+#[must_use]
+pub const fn align_up(addr: usize, align: usize) -> Option<usize> {
+    debug_assert!(
+        align.is_power_of_two(),
+        "alignment should be a power of two"
+    );
+    match addr.checked_add(align - 1) {
+        Some(bumped) => Some(bumped & !(align - 1)),
+        None => None,
+    }
+}
+
+/// Round `addr` down to a multiple of `align`.
+#[must_use]
+pub const fn align_down(addr: usize, align: usize) -> usize {
+    debug_assert!(
+        align.is_power_of_two(),
+        "alignment should be a power of two"
+    );
+    addr & !(align - 1)
+}
+
+/// Return true if `addr` is a multiple of `align`.
+#[must_use]
+pub const fn is_aligned(addr: usize, align: usize) -> bool {
+    debug_assert!(
+        align.is_power_of_two(),
+        "alignment should be a power of two"
+    );
+    addr & (align - 1) == 0
+}
+
+#[cfg(test)]
+mod align_tests {
+    use super::{align_down, align_up, is_aligned};
+
+    #[test]
+    fn align_up_test() {
+        assert_eq!(align_up(0, 4096), Some(0));
+        assert_eq!(align_up(1, 4096), Some(4096));
+        assert_eq!(align_up(4096, 4096), Some(4096));
+        assert_eq!(align_up(4097, 8), Some(4104));
+        assert_eq!(align_up(usize::MAX, 1), Some(usize::MAX));
+        assert_eq!(align_up(usize::MAX - 4095, 4096), Some(usize::MAX - 4095));
+        assert_eq!(align_up(usize::MAX - 4094, 4096), None);
+    }
+
+    #[test]
+    fn align_down_test() {
+        assert_eq!(align_down(0, 4096), 0);
+        assert_eq!(align_down(4095, 4096), 0);
+        assert_eq!(align_down(4097, 4096), 4096);
+        assert_eq!(align_down(usize::MAX, 4096), usize::MAX - 4095);
+    }
+
+    #[test]
+    fn is_aligned_test() {
+        assert!(is_aligned(0, 4096));
+        assert!(is_aligned(8192, 4096));
+        assert!(!is_aligned(8200, 4096));
+        assert!(is_aligned(usize::MAX, 1));
+        assert!(!is_aligned(usize::MAX, 2));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "alignment should be a power of two")]
+    fn non_power_of_two_alignment() {
+        let _ = align_up(100, 24);
+    }
+}
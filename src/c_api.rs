@@ -0,0 +1,190 @@
+//! C interface to `WildScreenAlloc`.
+//! The allocator must be registered by `register` before calling these functions from C.
+//!
+//! C `free` doesn't pass the size, but this allocator needs the layout to free an object.
+//! So `wsa_free` takes the size passed to `wsa_malloc`, and `wsa_aligned_free` takes
+//! the alignment and the size passed to `wsa_aligned_alloc`.
+
+use crate::align::is_aligned;
+use crate::lock::Mutex;
+use crate::WildScreenAlloc;
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::mem::align_of;
+
+/// Error number for invalid argument.
+pub const EINVAL: i32 = 22;
+/// Error number for out of memory.
+pub const ENOMEM: i32 = 12;
+
+/// Allocator used by the C interface.
+static ALLOCATOR: Mutex<Option<&'static WildScreenAlloc>> = Mutex::new(None);
+
+/// Register the allocator used by the C interface.
+/// Only the first registration takes effect.
+pub fn register(allocator: &'static WildScreenAlloc) {
+    ALLOCATOR.lock().get_or_insert(allocator);
+}
+
+/// Return the registered allocator.
+fn registered() -> Option<&'static WildScreenAlloc> {
+    *ALLOCATOR.lock()
+}
+
+/// Allocate `size` bytes aligned to `usize`.
+/// Return null if it fails or no allocator is registered.
+#[no_mangle]
+pub extern "C" fn wsa_malloc(size: usize) -> *mut u8 {
+    wsa_aligned_alloc(align_of::<usize>(), size)
+}
+
+/// Allocate `size` bytes aligned to `align`.
+/// Return null if it fails, `align` isn't a power of two or no allocator is registered.
+#[no_mangle]
+pub extern "C" fn wsa_aligned_alloc(align: usize, size: usize) -> *mut u8 {
+    match (registered(), Layout::from_size_align(size, align)) {
+        (Some(allocator), Ok(layout)) => unsafe { allocator.alloc(layout) },
+        _ => core::ptr::null_mut(),
+    }
+}
+
+/// Allocate zeroed memory for an array of `nmemb` elements of `size` bytes.
+/// Return null if `nmemb * size` overflows, it fails or no allocator is registered.
+/// Free it by `wsa_free` with `nmemb * size`.
+#[no_mangle]
+pub extern "C" fn wsa_calloc(nmemb: usize, size: usize) -> *mut u8 {
+    let Some(total_size) = nmemb.checked_mul(size) else {
+        return core::ptr::null_mut();
+    };
+
+    match (
+        registered(),
+        Layout::from_size_align(total_size, align_of::<usize>()),
+    ) {
+        (Some(allocator), Ok(layout)) => unsafe { allocator.alloc_zeroed(layout) },
+        _ => core::ptr::null_mut(),
+    }
+}
+
+/// Allocate `size` bytes aligned to `align` and store the address to `out`.
+/// Return 0 on success, `EINVAL` if `align` isn't a power of two multiple of pointer size
+/// and `ENOMEM` if it fails. `out` isn't modified on failure.
+/// Free it by `wsa_aligned_free` with the same `align` and `size`.
+/// # Safety
+/// `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn wsa_posix_memalign(out: *mut *mut u8, align: usize, size: usize) -> i32 {
+    if !align.is_power_of_two() || !is_aligned(align, core::mem::size_of::<*mut u8>()) {
+        return EINVAL;
+    }
+
+    let ptr = wsa_aligned_alloc(align, size);
+    if ptr.is_null() {
+        return ENOMEM;
+    }
+    out.write(ptr);
+    0
+}
+
+/// Free `ptr` allocated by `wsa_malloc`.
+/// Do nothing if `ptr` is null.
+/// # Safety
+/// `ptr` must be allocated by `wsa_malloc` with the same `size`.
+#[no_mangle]
+pub unsafe extern "C" fn wsa_free(ptr: *mut u8, size: usize) {
+    wsa_aligned_free(ptr, align_of::<usize>(), size);
+}
+
+/// Free `ptr` allocated by `wsa_aligned_alloc`.
+/// Do nothing if `ptr` is null.
+/// # Safety
+/// `ptr` must be allocated by `wsa_aligned_alloc` with the same `align` and `size`.
+#[no_mangle]
+pub unsafe extern "C" fn wsa_aligned_free(ptr: *mut u8, align: usize, size: usize) {
+    if ptr.is_null() {
+        return;
+    }
+
+    if let (Some(allocator), Ok(layout)) = (registered(), Layout::from_size_align(size, align)) {
+        allocator.dealloc(ptr, layout);
+    }
+}
+
+#[cfg(test)]
+mod c_api_tests {
+    use super::{
+        register, wsa_aligned_alloc, wsa_aligned_free, wsa_calloc, wsa_free, wsa_malloc,
+        wsa_posix_memalign, EINVAL,
+    };
+    use crate::{constants, WildScreenAlloc};
+    use alloc::alloc::Layout;
+    use alloc::boxed::Box;
+
+    const HEAP_SIZE: usize = 32 * constants::PAGE_SIZE;
+
+    /// Register an allocator on a leaked heap.
+    fn setup() {
+        super::ALLOCATOR.lock().get_or_insert_with(|| unsafe {
+            let heap_layout = Layout::from_size_align(HEAP_SIZE, constants::PAGE_SIZE).unwrap();
+            let heap = alloc::alloc::alloc(heap_layout);
+            Box::leak(Box::new(WildScreenAlloc::new(heap as usize, HEAP_SIZE)))
+        });
+        // later registrations are ignored.
+        register(Box::leak(Box::new(WildScreenAlloc::empty())));
+    }
+
+    #[test]
+    fn malloc_and_free() {
+        setup();
+        for size in [1, 100, 4096, 5000] {
+            let ptr = wsa_malloc(size);
+            assert!(!ptr.is_null());
+            unsafe {
+                ptr.write_bytes(0xff, size);
+                wsa_free(ptr, size);
+            }
+        }
+        unsafe { wsa_free(core::ptr::null_mut(), 8) };
+    }
+
+    #[test]
+    fn aligned_alloc_and_free() {
+        setup();
+        let ptr = wsa_aligned_alloc(256, 100);
+        assert!(!ptr.is_null());
+        assert!(crate::align::is_aligned(ptr as usize, 256));
+        unsafe { wsa_aligned_free(ptr, 256, 100) };
+
+        assert!(wsa_aligned_alloc(3, 100).is_null());
+    }
+
+    #[test]
+    fn calloc() {
+        setup();
+        let ptr = wsa_calloc(10, 30);
+        assert!(!ptr.is_null());
+        unsafe {
+            assert!(core::slice::from_raw_parts(ptr, 300)
+                .iter()
+                .all(|b| *b == 0));
+            wsa_free(ptr, 300);
+        }
+
+        assert!(wsa_calloc(usize::MAX / 2, 3).is_null());
+    }
+
+    #[test]
+    fn posix_memalign() {
+        setup();
+        let mut ptr = core::ptr::null_mut();
+        unsafe {
+            assert_eq!(wsa_posix_memalign(&mut ptr, 256, 100), 0);
+            assert!(!ptr.is_null());
+            assert!(crate::align::is_aligned(ptr as usize, 256));
+            wsa_aligned_free(ptr, 256, 100);
+
+            let mut invalid = core::ptr::null_mut();
+            assert_eq!(wsa_posix_memalign(&mut invalid, 3, 100), EINVAL);
+            assert!(invalid.is_null());
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Counters of the heap that can be read without taking the allocator lock.
+
+use crate::{HeapSnapshot, SlabAllocator, SlabSize};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Copy of `HeapSnapshot` in atomics.
+/// They are only written while the allocator lock is held, and each operation writes only the counters it changes.
+/// Writes are released and reads are acquired, so a reader that sees a free also sees its allocation.
+pub struct Counters {
+    allocations: AtomicUsize,
+    frees: AtomicUsize,
+    live_bytes: AtomicUsize,
+    peak_bytes: AtomicUsize,
+    live_objects: [AtomicUsize; SlabSize::ALL.len()],
+}
+
+/// Counters of `SlabAllocator` before an operation.
+pub struct Mark {
+    allocations: usize,
+    frees: usize,
+    live_bytes: usize,
+    peak_bytes: usize,
+}
+
+impl Mark {
+    /// Record the counters of `allocator`.
+    pub fn new(allocator: &SlabAllocator) -> Self {
+        Mark {
+            allocations: allocator.allocations,
+            frees: allocator.frees,
+            live_bytes: allocator.live_bytes,
+            peak_bytes: allocator.peak_bytes,
+        }
+    }
+}
+
+/// Add `value` to `counter`.
+/// Targets without read-modify-write instructions load and store it instead,
+/// which is enough because it is only written with the allocator lock held.
+fn add(counter: &AtomicUsize, value: usize) {
+    #[cfg(target_has_atomic = "ptr")]
+    counter.fetch_add(value, Ordering::Release);
+    #[cfg(not(target_has_atomic = "ptr"))]
+    counter.store(counter.load(Ordering::Relaxed) + value, Ordering::Release);
+}
+
+/// Subtract `value` from `counter`.
+fn sub(counter: &AtomicUsize, value: usize) {
+    #[cfg(target_has_atomic = "ptr")]
+    counter.fetch_sub(value, Ordering::Release);
+    #[cfg(not(target_has_atomic = "ptr"))]
+    counter.store(counter.load(Ordering::Relaxed) - value, Ordering::Release);
+}
+
+impl Counters {
+    /// Return counters that are all zero.
+    pub const fn new() -> Self {
+        Counters {
+            allocations: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+            live_bytes: AtomicUsize::new(0),
+            peak_bytes: AtomicUsize::new(0),
+            live_objects: [const { AtomicUsize::new(0) }; SlabSize::ALL.len()],
+        }
+    }
+
+    /// Store `snapshot`.
+    /// It must be called with the allocator lock held.
+    pub fn publish(&self, snapshot: &HeapSnapshot) {
+        self.allocations
+            .store(snapshot.allocations, Ordering::Release);
+        self.frees.store(snapshot.frees, Ordering::Release);
+        self.live_bytes
+            .store(snapshot.live_bytes, Ordering::Release);
+        self.peak_bytes
+            .store(snapshot.peak_bytes, Ordering::Release);
+        for (counter, &count) in self.live_objects.iter().zip(&snapshot.live_objects) {
+            counter.store(count, Ordering::Release);
+        }
+    }
+
+    /// Add what `allocator` changed since `mark` to the counters.
+    /// `allocated` and `freed` are the objects that the operation allocated and freed (null if none)
+    /// to find the size classes that changed.
+    /// It must be called with the allocator lock held.
+    pub fn update(
+        &self,
+        mark: &Mark,
+        allocator: &SlabAllocator,
+        allocated: *mut u8,
+        freed: *mut u8,
+    ) {
+        if allocator.allocations != mark.allocations {
+            add(&self.allocations, allocator.allocations - mark.allocations);
+            if let Some(index) = allocator.cache_index_of(allocated) {
+                add(&self.live_objects[index], 1);
+            }
+        }
+        if allocator.frees != mark.frees {
+            if let Some(index) = allocator.cache_index_of(freed) {
+                sub(&self.live_objects[index], 1);
+            }
+            add(&self.frees, allocator.frees - mark.frees);
+        }
+
+        if allocator.live_bytes > mark.live_bytes {
+            add(&self.live_bytes, allocator.live_bytes - mark.live_bytes);
+        } else if allocator.live_bytes < mark.live_bytes {
+            sub(&self.live_bytes, mark.live_bytes - allocator.live_bytes);
+        }
+        if allocator.peak_bytes != mark.peak_bytes {
+            self.peak_bytes
+                .store(allocator.peak_bytes, Ordering::Release);
+        }
+    }
+
+    /// Load the counters.
+    /// Each of them may be a little older than the others, but `frees` never exceeds `allocations`.
+    pub fn load(&self) -> HeapSnapshot {
+        let frees = self.frees.load(Ordering::Acquire);
+        HeapSnapshot {
+            allocations: self.allocations.load(Ordering::Acquire),
+            frees,
+            live_bytes: self.live_bytes.load(Ordering::Acquire),
+            peak_bytes: self.peak_bytes.load(Ordering::Acquire),
+            live_objects: core::array::from_fn(|index| {
+                self.live_objects[index].load(Ordering::Acquire)
+            }),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Ring buffer of recent allocation events.
+//! It is enabled by `event_log` feature.
+
+use crate::SlabSize;
+
+/// Number of events that are kept.
+pub const CAPACITY: usize = 64;
+
+/// Kind of an allocation event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    Alloc,
+    Dealloc,
+}
+
+/// An allocation event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// Whether the object was allocated or deallocated.
+    pub kind: EventKind,
+    /// Size class of the layout. `None` means that it doesn't fit any size class.
+    pub size_class: Option<SlabSize>,
+    /// Address of the object.
+    pub addr: usize,
+}
+
+/// Fixed-size ring buffer that keeps the last `CAPACITY` events.
+pub struct EventLog {
+    events: [Option<Event>; CAPACITY],
+    /// Index where the next event is written.
+    next: usize,
+}
+
+impl EventLog {
+    /// Return an empty log.
+    pub const fn new() -> Self {
+        EventLog {
+            events: [None; CAPACITY],
+            next: 0,
+        }
+    }
+
+    /// Record `event` overwriting the oldest one.
+    pub fn push(&mut self, event: Event) {
+        self.events[self.next] = Some(event);
+        self.next = (self.next + 1) % CAPACITY;
+    }
+
+    /// Iterate over events from the oldest to the newest.
+    pub fn iter(&self) -> impl Iterator<Item = Event> + '_ {
+        let (newer, older) = self.events.split_at(self.next);
+        older.iter().chain(newer).flatten().copied()
+    }
+}
@@ -0,0 +1,66 @@
+//! Allocator for requests that slab caches can't serve.
+
+use alloc::alloc::Layout;
+use core::ptr::NonNull;
+
+/// Allocator managing the rest of the heap after the regions of slab caches.
+/// It serves objects over 4096 bytes, objects of exhausted size classes and page runs.
+/// `linked_list_allocator::Heap` is used by default.
+pub trait Fallback {
+    /// Create the allocator that manages `size` bytes from `start_addr`.
+    /// `start_addr` is aligned to the page size.
+    /// # Safety
+    /// The region must be valid and unused.
+    unsafe fn init(start_addr: usize, size: usize) -> Self;
+
+    /// Allocates an object of `layout`.
+    /// Return null if it fails.
+    fn alloc_fallback(&mut self, layout: Layout) -> *mut u8;
+
+    /// Deallocate(free) object.
+    /// # Safety
+    /// `ptr` must be allocated by `alloc_fallback` with the same `layout`.
+    unsafe fn dealloc_fallback(&mut self, ptr: *mut u8, layout: Layout);
+
+    /// Return bytes currently allocated.
+    fn used_bytes(&self) -> usize;
+
+    /// Return bytes that an allocation of `layout` takes from `used_bytes`.
+    /// It is larger than `layout.size()` if the allocator rounds allocations up.
+    fn allocated_size(layout: Layout) -> usize {
+        layout.size()
+    }
+
+    /// Return bytes that can still be allocated.
+    fn free_bytes(&self) -> usize;
+}
+
+impl Fallback for linked_list_allocator::Heap {
+    unsafe fn init(start_addr: usize, size: usize) -> Self {
+        linked_list_allocator::Heap::new(start_addr as *mut u8, size)
+    }
+
+    fn alloc_fallback(&mut self, layout: Layout) -> *mut u8 {
+        match self.allocate_first_fit(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(()) => core::ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc_fallback(&mut self, ptr: *mut u8, layout: Layout) {
+        self.deallocate(NonNull::new(ptr).unwrap(), layout);
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.used()
+    }
+
+    fn allocated_size(layout: Layout) -> usize {
+        linked_list_allocator::hole::HoleList::align_layout(layout)
+            .map_or(layout.size(), |layout| layout.size())
+    }
+
+    fn free_bytes(&self) -> usize {
+        self.free()
+    }
+}
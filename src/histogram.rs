@@ -0,0 +1,74 @@
+//! Histogram of requested sizes.
+//! It is enabled by `stats-histogram` feature.
+
+use crate::SlabSize;
+use core::ops::RangeInclusive;
+
+/// Width of a bucket in bytes.
+pub const BUCKET_SIZE: usize = 8;
+/// Number of buckets covering sizes up to the largest size class.
+pub const NUM_OF_BUCKETS: usize = SlabSize::Slab4096Bytes as usize / BUCKET_SIZE;
+
+/// Counts of allocation requests bucketed by requested size.
+/// Each size class covers the buckets between the previous class and itself.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    /// `buckets[i]` counts requests whose size is in `Histogram::bucket_range(i)`.
+    pub buckets: [usize; NUM_OF_BUCKETS],
+    /// Number of requests over the largest size class.
+    pub oversized: usize,
+    /// Total bytes wasted by rounding up to the size class.
+    pub wasted_bytes: usize,
+}
+
+impl Histogram {
+    /// Return an empty histogram.
+    pub const fn new() -> Self {
+        Histogram {
+            buckets: [0; NUM_OF_BUCKETS],
+            oversized: 0,
+            wasted_bytes: 0,
+        }
+    }
+
+    /// Return the range of sizes counted by the `index`th bucket.
+    /// Size 0 is counted by the first bucket.
+    #[must_use]
+    pub const fn bucket_range(index: usize) -> RangeInclusive<usize> {
+        let start = if index == 0 {
+            0
+        } else {
+            index * BUCKET_SIZE + 1
+        };
+        start..=(index + 1) * BUCKET_SIZE
+    }
+
+    /// Return the number of requests whose size falls into `class`.
+    #[must_use]
+    pub fn class_count(&self, class: SlabSize) -> usize {
+        let index = SlabSize::ALL.iter().position(|c| *c == class).unwrap();
+        let start = match index {
+            0 => 0,
+            _ => SlabSize::ALL[index - 1] as usize / BUCKET_SIZE,
+        };
+        self.buckets[start..class as usize / BUCKET_SIZE]
+            .iter()
+            .sum()
+    }
+
+    /// Record a request of `size` bytes that wasted `waste` bytes.
+    pub(crate) fn record(&mut self, size: usize, waste: usize) {
+        match self.buckets.get_mut(size.saturating_sub(1) / BUCKET_SIZE) {
+            Some(bucket) => *bucket += 1,
+            None => self.oversized += 1,
+        }
+        self.wasted_bytes += waste;
+    }
+}
+
+impl Default for Histogram {
+    /// Same as `Histogram::new`.
+    fn default() -> Self {
+        Self::new()
+    }
+}
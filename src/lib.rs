@@ -2,307 +2,3584 @@
 
 extern crate alloc;
 extern crate linked_list_allocator;
+#[cfg(test)]
+extern crate std;
+
+pub mod align;
+#[cfg(feature = "c_api")]
+pub mod c_api;
+mod counters;
+#[cfg(feature = "event_log")]
+mod event;
+mod fallback;
+#[cfg(feature = "stats-histogram")]
+mod histogram;
+mod lock;
+#[cfg(any(feature = "track_caller", feature = "alloc-tags"))]
+mod side_table;
+mod slab;
+#[cfg(feature = "alloc-tags")]
+mod tags;
+#[cfg(feature = "track_caller")]
+mod track;
+
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::ops::Range;
+use core::ptr::NonNull;
+#[cfg(feature = "failpoints")]
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use lock::Mutex;
+use slab::SlabCache;
+
+#[cfg(feature = "event_log")]
+pub use event::{Event, EventKind};
+pub use fallback::Fallback;
+#[cfg(feature = "stats-histogram")]
+pub use histogram::Histogram;
+pub use slab::{SlabInfo, SlabKind, SlabSize};
+#[cfg(feature = "alloc-tags")]
+pub use tags::{TagStats, NUM_OF_TAGS};
+
+/// Constants.
+mod constants {
+    /// Number of slab allocator size.
+    pub const NUM_OF_SLABS: usize = 16;
+    /// Number of ranges that can be claimed at the same time.
+    pub const MAX_CLAIMS: usize = 4;
+    /// Page size.
+    #[cfg(not(feature = "page_size_16k"))]
+    pub const PAGE_SIZE: usize = 4096;
+    /// Page size.
+    #[cfg(feature = "page_size_16k")]
+    pub const PAGE_SIZE: usize = 16384;
+}
+
+/// Error returned by the fallible APIs of `SlabAllocator`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AllocError {
+    /// `prealloc` can't reserve enough objects.
+    Insufficient {
+        /// Number of objects that are available.
+        available: usize,
+    },
+    /// The allocation needs the fallback, but the allocator is frozen by `freeze`.
+    Frozen,
+    /// The heap has no room for the allocation.
+    OutOfMemory,
+}
+
+/// Error returned by `SlabAllocator::claim` and `SlabAllocator::release`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClaimError {
+    /// The range is out of the regions of the size classes.
+    OutOfRange,
+    /// The range has no bytes.
+    Empty,
+    /// An object in the range is allocated.
+    InUse,
+    /// Too many ranges are claimed at the same time.
+    TooManyClaims,
+    /// The range isn't claimed by `claim`.
+    NotClaimed,
+}
+
+/// Statistics of the heap returned by `SlabAllocator::stats`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Size of the heap.
+    pub heap_size: usize,
+    /// Bytes currently allocated.
+    pub used_bytes: usize,
+    /// The largest bytes allocated at the same time.
+    pub peak_bytes: usize,
+    /// Total bytes wasted by rounding up to the size class since the allocator was created.
+    /// It only grows: freeing an object doesn't give back the bytes wasted by it.
+    pub total_internal_fragmentation: usize,
+    /// Usage of the heap by each tag.
+    #[cfg(feature = "alloc-tags")]
+    pub tags: [TagStats; NUM_OF_TAGS],
+    /// Number of allocations that couldn't be attributed to a tag because the table was full.
+    #[cfg(feature = "alloc-tags")]
+    pub untagged: usize,
+}
+
+/// Counters of the heap captured at once by `SlabAllocator::snapshot`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeapSnapshot {
+    /// Number of successful allocations since the allocator was created.
+    pub allocations: usize,
+    /// Number of deallocations since the allocator was created.
+    pub frees: usize,
+    /// Bytes currently handed out.
+    /// Slab objects are counted at their size class.
+    pub live_bytes: usize,
+    /// The largest `live_bytes` since the allocator was created or the peak was reset.
+    pub peak_bytes: usize,
+    /// Number of live objects of each size class in the order of `SlabSize::ALL`.
+    pub live_objects: [usize; SlabSize::ALL.len()],
+}
+
+/// Slab allocator that provide global allocator.
+/// If allocate size over 4096 bytes or the size class is exhausted, it delegate to the fallback `F`
+/// (`linked_list_allocator` by default).
+///
+/// It is `Send` because it exclusively owns the heap region (free lists are only reached through it),
+/// but it isn't `Sync` because `linked_list_allocator::Heap` isn't.
+/// Share it between threads through `WildScreenAlloc`, which is `Sync` by the lock.
+pub struct SlabAllocator<F: Fallback = linked_list_allocator::Heap> {
+    /// Slab caches for each size in `SlabSize::ALL`.
+    caches: [SlabCache; SlabSize::ALL.len()],
+    /// Allocator for the rest of the heap.
+    fallback: F,
+    /// Start address of the heap.
+    start_addr: usize,
+    /// Size of the heap.
+    heap_size: usize,
+    /// Total bytes wasted by rounding up to the size class.
+    internal_fragmentation: usize,
+    /// Bytes currently handed out.
+    /// Slab objects are counted at their size class, and objects in the fallback at the size it takes.
+    live_bytes: usize,
+    /// The largest `live_bytes` since the allocator was created or the peak was reset.
+    peak_bytes: usize,
+    /// Ranges currently claimed by `claim`.
+    claims: [Option<Range<usize>>; constants::MAX_CLAIMS],
+    /// Number of objects allocated from each size class. It is never decremented.
+    allocation_counts: [usize; SlabSize::ALL.len()],
+    /// Number of successful allocations.
+    allocations: usize,
+    /// Number of deallocations.
+    frees: usize,
+    /// Allocations from the fallback fail while it is true.
+    frozen: bool,
+    /// Call sites of live allocations.
+    #[cfg(feature = "track_caller")]
+    callers: track::CallerTable,
+    /// Tags of live allocations.
+    #[cfg(feature = "alloc-tags")]
+    tags: tags::TagTable,
+    /// Recent allocation events.
+    #[cfg(feature = "event_log")]
+    events: event::EventLog,
+    /// Histogram of requested sizes.
+    #[cfg(feature = "stats-histogram")]
+    histogram: Histogram,
+}
+
+impl SlabAllocator {
+    /// Return new `SlabAllocator` that uses `linked_list_allocator` as the fallback.
+    /// # Safety
+    /// `start_addr` must be aligned to the page size.
+    ///
+    /// # Panics
+    /// If `start_addr` isn't aligned to the page size, this function will panic.
+    /// It also panics if `heap_size` is less than 16 pages, because the heap is split into 16 page aligned regions
+    /// of the same size (15 for the size classes and one for the fallback) and each of them needs a page.
+    #[must_use]
+    pub unsafe fn new(start_addr: usize, heap_size: usize) -> Self {
+        Self::with_fallback(start_addr, heap_size)
+    }
+
+    /// Return the layout used to allocate `layout` from `linked_list_allocator`.
+    /// Objects of a size class keep the alignment of the class even if they are allocated from it.
+    fn fallback_layout(layout: Layout) -> Layout {
+        match Self::get_slab_size(&layout) {
+            Some(class) => layout.align_to(class.align()).unwrap(),
+            None => layout,
+        }
+    }
+
+    /// Return the layout of `n_pages` pages aligned to `align`.
+    fn pages_layout(n_pages: usize, align: usize) -> Option<Layout> {
+        let size = n_pages.checked_mul(constants::PAGE_SIZE)?;
+        Layout::from_size_align(size, align.max(constants::PAGE_SIZE)).ok()
+    }
+
+    /// Return the size class that `layout` will be allocated from.
+    /// `None` means that it is delegated to `linked_list_allocator`.
+    #[must_use]
+    pub fn size_class_for(layout: &Layout) -> Option<SlabSize> {
+        Self::get_slab_size(layout)
+    }
+
+    /// Return the bytes wasted by rounding `layout` up to its size class.
+    /// Allocations delegated to `linked_list_allocator` waste nothing.
+    #[must_use]
+    pub fn waste_for(layout: &Layout) -> usize {
+        Self::get_slab_size(layout).map_or(0, |size| size as usize - layout.size())
+    }
+
+    /// Return the size of the smallest size class.
+    #[must_use]
+    pub const fn min_allocation_size() -> usize {
+        SlabSize::ALL[0] as usize
+    }
+
+    /// Return the size of the largest size class.
+    /// Larger objects are allocated from `linked_list_allocator`.
+    #[must_use]
+    pub const fn max_slab_size() -> usize {
+        SlabSize::ALL[SlabSize::ALL.len() - 1] as usize
+    }
+
+    /// Return the size of the region of each slab cache.
+    /// Each region is page aligned so that objects of 4096 bytes class are also page aligned.
+    const fn slab_allocated_size(heap_size: usize) -> usize {
+        align::align_down(heap_size / constants::NUM_OF_SLABS, constants::PAGE_SIZE)
+    }
+
+    /// Convert `layout.size` to `SlabSize`
+    fn get_slab_size(layout: &Layout) -> Option<SlabSize> {
+        Self::get_class_index(layout).map(|index| SlabSize::ALL[index])
+    }
+
+    /// Return the index of the cache for `layout`.
+    /// It is the smallest size class that satisfies both of size and alignment.
+    /// Return `None` if no size class can satisfy them (e.g. alignment is over 4096).
+    fn get_class_index(layout: &Layout) -> Option<usize> {
+        let index = SlabSize::index_of(layout.size())?;
+
+        SlabSize::ALL[index..]
+            .iter()
+            .position(|class| layout.align() <= class.align())
+            .map(|offset| index + offset)
+    }
+}
+
+impl<F: Fallback> SlabAllocator<F> {
+    /// Return new `SlabAllocator` that uses `F` as the fallback.
+    /// # Safety
+    /// `start_addr` must be aligned to the page size.
+    ///
+    /// # Panics
+    /// If `start_addr` isn't aligned to the page size, this function will panic.
+    /// It also panics if `heap_size` is less than 16 pages, because the heap is split into 16 page aligned regions
+    /// of the same size (15 for the size classes and one for the fallback) and each of them needs a page.
+    #[must_use]
+    pub unsafe fn with_fallback(start_addr: usize, heap_size: usize) -> Self {
+        assert!(
+            align::is_aligned(start_addr, constants::PAGE_SIZE),
+            "Start address should be page aligned"
+        );
+        assert!(
+            heap_size >= constants::NUM_OF_SLABS * constants::PAGE_SIZE,
+            "Heap must be at least 16 pages"
+        );
+
+        // The rest of the heap including the tail that doesn't fill a page is given to the fallback.
+        let slab_allocated_size = SlabAllocator::slab_allocated_size(heap_size);
+        let num_of_caches = SlabSize::ALL.len();
+        SlabAllocator {
+            caches: core::array::from_fn(|index| {
+                SlabCache::new(
+                    start_addr + index * slab_allocated_size,
+                    slab_allocated_size,
+                    SlabSize::ALL[index],
+                )
+            }),
+            fallback: F::init(
+                start_addr + num_of_caches * slab_allocated_size,
+                heap_size - num_of_caches * slab_allocated_size,
+            ),
+            start_addr,
+            heap_size,
+            internal_fragmentation: 0,
+            live_bytes: 0,
+            peak_bytes: 0,
+            claims: [const { None }; constants::MAX_CLAIMS],
+            allocation_counts: [0; SlabSize::ALL.len()],
+            allocations: 0,
+            frees: 0,
+            frozen: false,
+            #[cfg(feature = "track_caller")]
+            callers: track::CallerTable::new(),
+            #[cfg(feature = "alloc-tags")]
+            tags: tags::TagTable::new(),
+            #[cfg(feature = "event_log")]
+            events: event::EventLog::new(),
+            #[cfg(feature = "stats-histogram")]
+            histogram: Histogram::new(),
+        }
+    }
+
+    /// Reclaim all outstanding allocations and re-initialize the allocator as freshly created.
+    /// # Safety
+    /// All pointers allocated from this allocator become invalid.
+    pub unsafe fn reset(&mut self) {
+        *self = Self::with_fallback(self.start_addr, self.heap_size);
+    }
+
+    /// Return the start address of the heap.
+    #[must_use]
+    pub fn start_addr(&self) -> usize {
+        self.start_addr
+    }
+
+    /// Return the size of the heap.
+    #[must_use]
+    pub fn heap_size(&self) -> usize {
+        self.heap_size
+    }
+
+    /// Return the address range of the heap.
+    #[must_use]
+    pub fn region(&self) -> Range<usize> {
+        self.start_addr..self.start_addr + self.heap_size
+    }
+
+    /// Make allocations that need the fallback fail fast (e.g. during a real-time phase).
+    /// Objects that fit the free objects of their size class are still allocated,
+    /// and deallocation is not affected.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    /// Allow allocations from the fallback again.
+    pub fn thaw(&mut self) {
+        self.frozen = false;
+    }
+
+    /// Return true if the allocator is frozen by `freeze`.
+    #[must_use]
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Discard the allocator without any cleanup attempt.
+    /// The heap region is left as it is.
+    pub fn forget_region(self) {
+        core::mem::forget(self);
+    }
+
+    /// Return bytes currently allocated.
+    /// Slab objects are counted at their size class, and objects in the fallback at the size it actually takes.
+    /// It is the same counter as `live_bytes` of `HeapSnapshot`, so it never exceeds the peak.
+    #[must_use]
+    pub fn used_bytes(&self) -> usize {
+        self.live_bytes
+    }
+
+    /// Iterate over the information of slabs of all size classes in ascending order of size.
+    /// It doesn't modify the free lists.
+    pub fn iter_all_slabs(&self) -> impl Iterator<Item = SlabInfo> + '_ {
+        self.caches.iter().map(SlabCache::info)
+    }
+
+    /// Call `f` with the address and the size class of every live object in the size classes.
+    /// Objects allocated from the fallback are not visited because it doesn't track them.
+    /// It doesn't modify the free lists, and walks them once per 4096 objects of a class.
+    pub fn walk_live(&self, mut f: impl FnMut(*const u8, usize)) {
+        for (cache, size) in self.caches.iter().zip(SlabSize::ALL) {
+            cache.for_each_live(|object| f(object, size as usize));
+        }
+    }
+
+    /// Mark objects that overlap `addr..addr + size` as allocated so that they are never handed out
+    /// (e.g. an initial ramdisk placed in the heap by the bootloader).
+    /// Return the range by `release` with the same `addr` and `size`.
+    /// Objects in the range are counted in `used_bytes`.
+    /// Up to 4 ranges can be claimed at the same time.
+    ///
+    /// # Errors
+    /// It fails without claiming anything if the range is empty, is out of the regions of the size classes
+    /// (including when its end overflows), an object in the range is allocated (including by another claim) or too many ranges are claimed.
+    pub fn claim(&mut self, addr: usize, size: usize) -> Result<(), ClaimError> {
+        if size == 0 {
+            return Err(ClaimError::Empty);
+        }
+        let end = addr.checked_add(size).ok_or(ClaimError::OutOfRange)?;
+        let range = addr..end;
+        if range.start < self.start_addr || self.fallback_region().start < range.end {
+            return Err(ClaimError::OutOfRange);
+        }
+        let slot = self
+            .claims
+            .iter()
+            .position(Option::is_none)
+            .ok_or(ClaimError::TooManyClaims)?;
+        if !self
+            .caches
+            .iter()
+            .all(|cache| cache.is_free_range(range.clone()))
+        {
+            return Err(ClaimError::InUse);
+        }
+
+        for (index, cache) in self.caches.iter_mut().enumerate() {
+            let claimed = cache.claim(range.clone());
+            self.live_bytes += claimed * SlabSize::ALL[index] as usize;
+        }
+        self.peak_bytes = self.peak_bytes.max(self.live_bytes);
+        self.claims[slot] = Some(range);
+        Ok(())
+    }
+
+    /// Return objects claimed by `claim` with the same `addr` and `size` to the allocator.
+    /// # Safety
+    /// The range must no longer be used.
+    ///
+    /// # Errors
+    /// It fails without releasing anything if the range isn't claimed (e.g. it is already released).
+    pub unsafe fn release(&mut self, addr: usize, size: usize) -> Result<(), ClaimError> {
+        let end = addr.checked_add(size).ok_or(ClaimError::NotClaimed)?;
+        let range = addr..end;
+        let slot = self
+            .claims
+            .iter()
+            .position(|claim| claim.as_ref() == Some(&range))
+            .ok_or(ClaimError::NotClaimed)?;
+
+        self.claims[slot] = None;
+        for (index, cache) in self.caches.iter_mut().enumerate() {
+            let released = cache.release(range.clone());
+            self.live_bytes -= released * SlabSize::ALL[index] as usize;
+        }
+        Ok(())
+    }
+
+    /// Return the largest size that can be allocated when nothing is allocated from the fallback.
+    /// It is the capacity of the fallback, which can be smaller than its region
+    /// (e.g. `linked_list_allocator` can't use the tail that is not a multiple of the word size).
+    #[must_use]
+    pub fn max_allocation_size(&self) -> usize {
+        self.fallback.used_bytes() + self.fallback.free_bytes()
+    }
+
+    /// Return bytes currently allocated from the fallback.
+    /// It includes objects spilled from exhausted size classes.
+    #[must_use]
+    pub fn fallback_used_bytes(&self) -> usize {
+        self.fallback.used_bytes()
+    }
+
+    /// Return bytes that the fallback can still allocate.
+    #[must_use]
+    pub fn fallback_free_bytes(&self) -> usize {
+        self.fallback.free_bytes()
+    }
+
+    /// Return the histogram of requested sizes of successful allocations.
+    #[cfg(feature = "stats-histogram")]
+    #[must_use]
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+
+    /// Return the largest number of bytes handed out at the same time
+    /// since the allocator was created or `reset_peak` was called.
+    /// Slab objects are counted at their size class.
+    #[must_use]
+    pub fn peak_bytes(&self) -> usize {
+        self.peak_bytes
+    }
+
+    /// Reset the peak to the bytes currently handed out.
+    pub fn reset_peak(&mut self) {
+        self.peak_bytes = self.live_bytes;
+    }
+
+    /// Return statistics of the heap.
+    #[must_use]
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            heap_size: self.heap_size,
+            used_bytes: self.used_bytes(),
+            peak_bytes: self.peak_bytes,
+            total_internal_fragmentation: self.internal_fragmentation,
+            #[cfg(feature = "alloc-tags")]
+            tags: self.tags.stats(),
+            #[cfg(feature = "alloc-tags")]
+            untagged: self.tags.overflow(),
+        }
+    }
+
+    /// Set the tag attributed to following allocations and return the previous one.
+    /// Deallocations are attributed to the tag recorded at allocation.
+    ///
+    /// # Panics
+    /// If `tag` isn't less than `NUM_OF_TAGS`, it will panic.
+    #[cfg(feature = "alloc-tags")]
+    pub fn set_tag(&mut self, tag: u8) -> u8 {
+        self.tags.set_current(tag)
+    }
+
+    /// Capture the counters of the heap at once.
+    /// Objects allocated by `allocate_pages` are not counted in `allocations` and `frees`.
+    #[must_use]
+    pub fn snapshot(&self) -> HeapSnapshot {
+        HeapSnapshot {
+            allocations: self.allocations,
+            frees: self.frees,
+            live_bytes: self.live_bytes,
+            peak_bytes: self.peak_bytes,
+            live_objects: core::array::from_fn(|index| self.caches[index].info().used_objects),
+        }
+    }
+
+    /// Return the number of objects allocated from each size class in the order of `SlabSize::ALL`
+    /// since the allocator was created or `reset_histogram` was called.
+    /// Unlike `iter_all_slabs`, frees don't decrease it.
+    #[must_use]
+    pub fn allocation_histogram(&self) -> [usize; SlabSize::ALL.len()] {
+        self.allocation_counts
+    }
+
+    /// Reset the counts of `allocation_histogram` to 0.
+    pub fn reset_histogram(&mut self) {
+        self.allocation_counts = [0; SlabSize::ALL.len()];
+    }
+
+    /// Ensure that at least `count` objects of `layout` can be allocated from its size class
+    /// without falling back (e.g. before a real-time phase).
+    /// Every region of size classes is populated when the allocator is created,
+    /// so it only checks the number of free objects.
+    /// Return `AllocError::Insufficient` with the number of available objects if they are not enough.
+    /// Layouts that have no size class have no objects to reserve.
+    pub fn prealloc(&self, layout: Layout, count: usize) -> Result<(), AllocError> {
+        let available = SlabAllocator::get_class_index(&layout)
+            .map_or(0, |index| self.caches[index].free_count());
+
+        if available >= count {
+            Ok(())
+        } else {
+            Err(AllocError::Insufficient { available })
+        }
+    }
+
+    /// Allocates a new object.
+    /// If the size class for `layout` is exhausted, it is allocated from `linked_list_allocator` instead.
+    /// Alignment over the page size (e.g. for a page table root) is also served by it,
+    /// which pads the block to carve out the aligned address.
+    #[cfg_attr(feature = "track_caller", track_caller)]
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        self.allocate_object(layout, false)
+    }
+
+    /// Same as `allocate`, but return the reason instead of null if it fails.
+    /// `AllocError::Frozen` means that the allocation needed the fallback while the allocator is frozen.
+    #[cfg_attr(feature = "track_caller", track_caller)]
+    pub fn try_allocate(&mut self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        match NonNull::new(self.allocate_object(layout, false)) {
+            Some(ptr) => Ok(ptr),
+            None if self.frozen => Err(AllocError::Frozen),
+            None => Err(AllocError::OutOfMemory),
+        }
+    }
+
+    /// Allocates a new object whose first `layout.size()` bytes are zeroed.
+    /// Objects of a size class are zeroed by `SlabCache::allocate_zeroed`.
+    #[cfg_attr(feature = "track_caller", track_caller)]
+    pub fn allocate_zeroed(&mut self, layout: Layout) -> *mut u8 {
+        self.allocate_object(layout, true)
+    }
+
+    /// Allocates a new object and zero it if `zeroed` is true.
+    #[cfg_attr(feature = "track_caller", track_caller)]
+    fn allocate_object(&mut self, layout: Layout, zeroed: bool) -> *mut u8 {
+        let ptr = match SlabAllocator::get_class_index(&layout) {
+            Some(index) => {
+                let cache = &mut self.caches[index];
+                let ptr = if zeroed {
+                    cache.allocate_zeroed()
+                } else {
+                    cache.allocate()
+                };
+
+                if ptr.is_null() {
+                    self.allocate_from_fallback(layout, zeroed)
+                } else {
+                    self.internal_fragmentation += SlabAllocator::waste_for(&layout);
+                    self.add_live_bytes(SlabSize::ALL[index] as usize);
+                    self.allocation_counts[index] += 1;
+                    ptr
+                }
+            }
+            None => self.allocate_from_fallback(layout, zeroed),
+        };
+
+        if !ptr.is_null() {
+            self.allocations += 1;
+        }
+
+        #[cfg(feature = "track_caller")]
+        if !ptr.is_null() {
+            self.callers.insert(ptr, core::panic::Location::caller());
+        }
+
+        #[cfg(feature = "alloc-tags")]
+        if !ptr.is_null() {
+            self.tags.insert(ptr, layout.size());
+        }
+
+        #[cfg(feature = "event_log")]
+        if !ptr.is_null() {
+            self.record_event(EventKind::Alloc, &layout, ptr);
+        }
+
+        #[cfg(feature = "stats-histogram")]
+        if !ptr.is_null() {
+            self.histogram
+                .record(layout.size(), SlabAllocator::waste_for(&layout));
+        }
+
+        #[cfg(feature = "defmt")]
+        if ptr.is_null() {
+            defmt::trace!(
+                "out of memory: size {=usize}, align {=usize}, slab class {=usize}",
+                layout.size(),
+                layout.align(),
+                SlabAllocator::get_slab_size(&layout).map_or(0, |size| size as usize)
+            );
+        }
+        ptr
+    }
+
+    /// Allocates `size` bytes aligned to `align`.
+    /// Return null if `align` isn't a power of two, the layout is invalid or it fails.
+    /// The object must be freed by `deallocate_aligned` with the same `size` and `align`.
+    #[cfg_attr(feature = "track_caller", track_caller)]
+    pub fn allocate_aligned(&mut self, size: usize, align: usize) -> *mut u8 {
+        match Layout::from_size_align(size, align) {
+            Ok(layout) => self.allocate(layout),
+            Err(_) => core::ptr::null_mut(),
+        }
+    }
+
+    /// Deallocate object allocated by `allocate_aligned`.
+    /// # Safety
+    /// `ptr` must be allocated by `allocate_aligned` with the same `size` and `align`.
+    pub unsafe fn deallocate_aligned(&mut self, ptr: *mut u8, size: usize, align: usize) {
+        self.deallocate(ptr, Layout::from_size_align_unchecked(size, align));
+    }
+
+    /// Allocates up to `n` objects of `layout` into `out` in one pass.
+    /// Objects that the size class can't provide are allocated from `linked_list_allocator`.
+    /// Return how many objects were allocated.
+    #[cfg_attr(feature = "track_caller", track_caller)]
+    pub fn allocate_batch(&mut self, layout: Layout, n: usize, out: &mut [*mut u8]) -> usize {
+        let mut count = 0;
+        if let Some(index) = SlabAllocator::get_class_index(&layout) {
+            count = self.caches[index].allocate_batch(n, out);
+            self.internal_fragmentation += SlabAllocator::waste_for(&layout) * count;
+            self.add_live_bytes(SlabSize::ALL[index] as usize * count);
+            self.allocation_counts[index] += count;
+        }
+
+        for slot in out.iter_mut().take(n).skip(count) {
+            let ptr = self.allocate_from_fallback(layout, false);
+            if ptr.is_null() {
+                break;
+            }
+            *slot = ptr;
+            count += 1;
+        }
+
+        self.allocations += count;
+        #[cfg(feature = "track_caller")]
+        for ptr in &out[..count] {
+            self.callers.insert(*ptr, core::panic::Location::caller());
+        }
+        #[cfg(feature = "alloc-tags")]
+        for ptr in &out[..count] {
+            self.tags.insert(*ptr, layout.size());
+        }
+        #[cfg(feature = "event_log")]
+        for ptr in &out[..count] {
+            self.record_event(EventKind::Alloc, &layout, *ptr);
+        }
+        #[cfg(feature = "stats-histogram")]
+        for _ in 0..count {
+            self.histogram
+                .record(layout.size(), SlabAllocator::waste_for(&layout));
+        }
+        count
+    }
+
+    /// Deallocate(free) object.
+    /// # Safety
+    /// Given pointer must be valid.
+    ///
+    /// A null pointer is ignored like `free(NULL)`.
+    ///
+    /// # Panics
+    /// With `debug_checks` feature, it panics if the size class of `layout`
+    /// doesn't match the region that contains `ptr`, or if `ptr` is out of the heap.
+    /// Without it, a pointer out of the heap is ignored.
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        if ptr.is_null() {
+            return;
+        }
+        if !self.region().contains(&(ptr as usize)) {
+            #[cfg(feature = "debug_checks")]
+            panic!("Pointer is not owned by the allocator");
+            #[cfg(not(feature = "debug_checks"))]
+            return;
+        }
+
+        self.frees += 1;
+        #[cfg(feature = "track_caller")]
+        self.callers.remove(ptr);
+        #[cfg(feature = "alloc-tags")]
+        self.tags.remove(ptr);
+        #[cfg(feature = "event_log")]
+        self.record_event(EventKind::Dealloc, &layout, ptr);
+
+        if self.is_fallback_object(ptr) {
+            let fallback_layout = SlabAllocator::fallback_layout(layout);
+            self.fallback.dealloc_fallback(ptr, fallback_layout);
+            self.live_bytes -= F::allocated_size(fallback_layout);
+            return;
+        }
+
+        #[cfg(feature = "debug_checks")]
+        assert_eq!(
+            SlabAllocator::get_class_index(&layout),
+            self.cache_index_of(ptr),
+            "Layout doesn't match the size class of the object"
+        );
+
+        match SlabAllocator::get_class_index(&layout) {
+            Some(index) => {
+                self.caches[index].deallocate(ptr);
+                self.live_bytes -= SlabSize::ALL[index] as usize;
+            }
+            None => panic!("Object of this layout must be in the fallback"),
+        }
+    }
+
+    /// Write each live allocation and the location where it was allocated to `w`.
+    /// Allocations over the capacity of the side table are only counted.
+    #[cfg(feature = "track_caller")]
+    pub fn dump_leaks(&self, w: &mut impl core::fmt::Write) -> core::fmt::Result {
+        self.callers.dump(w)
+    }
+
+    /// Iterate over the last 64 allocation events from the oldest to the newest.
+    #[cfg(feature = "event_log")]
+    pub fn recent_events(&self) -> impl Iterator<Item = Event> + '_ {
+        self.events.iter()
+    }
+
+    /// Record an allocation event of `layout`.
+    #[cfg(feature = "event_log")]
+    fn record_event(&mut self, kind: EventKind, layout: &Layout, ptr: *mut u8) {
+        self.events.push(Event {
+            kind,
+            size_class: SlabAllocator::get_slab_size(layout),
+            addr: ptr as usize,
+        });
+    }
+
+    /// Deallocate(free) object without its layout.
+    /// The size class is looked up from the address.
+    /// Return false and do nothing if `ptr` isn't in the region of a size class,
+    /// because objects in `linked_list_allocator` can't be freed without their layout.
+    /// # Safety
+    /// Given pointer must be valid.
+    pub unsafe fn deallocate_unsized(&mut self, ptr: *mut u8) -> bool {
+        match self.cache_index_of(ptr) {
+            Some(index) => {
+                let class = SlabSize::ALL[index] as usize;
+                self.deallocate(ptr, Layout::from_size_align_unchecked(class, 1));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Allocates `layout` from the fallback and zero it if `zeroed` is true.
+    fn allocate_from_fallback(&mut self, layout: Layout, zeroed: bool) -> *mut u8 {
+        if self.frozen {
+            return core::ptr::null_mut();
+        }
+
+        let fallback_layout = SlabAllocator::fallback_layout(layout);
+        let ptr = self.fallback.alloc_fallback(fallback_layout);
+        if !ptr.is_null() {
+            self.add_live_bytes(F::allocated_size(fallback_layout));
+            if zeroed {
+                unsafe { ptr.write_bytes(0, layout.size()) };
+            }
+        }
+        ptr
+    }
+
+    /// Count `bytes` as handed out and update the peak.
+    fn add_live_bytes(&mut self, bytes: usize) {
+        self.live_bytes += bytes;
+        self.peak_bytes = self.peak_bytes.max(self.live_bytes);
+    }
+
+    /// Return true if `ptr` is in the region of the fallback.
+    fn is_fallback_object(&self, ptr: *mut u8) -> bool {
+        self.fallback_region().contains(&(ptr as usize))
+    }
+
+    /// Return the address range of the region given to the fallback.
+    fn fallback_region(&self) -> Range<usize> {
+        let slab_allocated_size = SlabAllocator::slab_allocated_size(self.heap_size);
+        self.start_addr + self.caches.len() * slab_allocated_size..self.region().end
+    }
+
+    /// Allocates `n_pages` contiguous pages aligned to `align` (e.g. for DMA buffers).
+    /// `align` under the page size is raised to the page size.
+    /// They are allocated from `linked_list_allocator`, so `n_pages` doesn't need to be a power of two
+    /// and no pages are wasted by rounding.
+    /// Return `None` if it fails, `align` isn't a power of two or the allocator is frozen.
+    pub fn allocate_pages(&mut self, n_pages: usize, align: usize) -> Option<NonNull<u8>> {
+        if self.frozen {
+            return None;
+        }
+        let layout = SlabAllocator::pages_layout(n_pages, align)?;
+        let ptr = NonNull::new(self.fallback.alloc_fallback(layout))?;
+        self.add_live_bytes(F::allocated_size(layout));
+        Some(ptr)
+    }
+
+    /// Deallocate pages allocated by `allocate_pages`.
+    /// # Safety
+    /// `ptr` must be allocated by `allocate_pages` with the same `n_pages` and `align`.
+    pub unsafe fn deallocate_pages(&mut self, ptr: NonNull<u8>, n_pages: usize, align: usize) {
+        let layout = SlabAllocator::pages_layout(n_pages, align).unwrap();
+        self.fallback.dealloc_fallback(ptr.as_ptr(), layout);
+        self.live_bytes -= F::allocated_size(layout);
+    }
+
+    /// Reallocate object to `new_size` bytes.
+    /// If `new_size` stays in the size class that holds the object, it returns the same pointer without copying.
+    /// Otherwise it allocates a new object from the size class (or `linked_list_allocator`) for `new_size`,
+    /// copies the contents and frees the old object by its original size class.
+    /// Return null and keep the old object if the allocation fails.
+    /// # Safety
+    /// Given pointer must be valid and allocated with `layout`.
+    /// `new_size` rounded up to `layout.align()` must not overflow `isize`.
+    #[cfg_attr(feature = "track_caller", track_caller)]
+    pub unsafe fn reallocate(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if self.fits_in_place(ptr, layout, new_size) {
+            self.resized_in_place(ptr, new_size);
+            return ptr;
+        }
+
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.allocate(new_layout);
+        if !new_ptr.is_null() {
+            core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.deallocate(ptr, layout);
+        }
+
+        new_ptr
+    }
+
+    /// Same as `reallocate`, but the bytes from `layout.size()` to `new_size` are zeroed
+    /// even if the object is resized in place.
+    /// # Safety
+    /// Same as `reallocate`.
+    #[cfg_attr(feature = "track_caller", track_caller)]
+    pub unsafe fn reallocate_zeroed(
+        &mut self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        let new_ptr = self.reallocate(ptr, layout, new_size);
+        if !new_ptr.is_null() && new_size > layout.size() {
+            new_ptr
+                .add(layout.size())
+                .write_bytes(0, new_size - layout.size());
+        }
+        new_ptr
+    }
+
+    /// Try to grow the object to `new_size` bytes without moving it.
+    /// It succeeds if `new_size` still fits the size class that actually holds the object.
+    /// Objects in `linked_list_allocator` can't grow in place.
+    /// After it succeeds, the object must be freed with `new_size`.
+    /// # Safety
+    /// Given pointer must be valid and allocated with `old_layout`.
+    #[cfg_attr(feature = "track_caller", track_caller)]
+    pub unsafe fn grow_in_place(
+        &mut self,
+        ptr: *mut u8,
+        old_layout: Layout,
+        new_size: usize,
+    ) -> bool {
+        let grown = new_size >= old_layout.size() && self.fits_in_place(ptr, old_layout, new_size);
+        if grown {
+            self.resized_in_place(ptr, new_size);
+        }
+        grown
+    }
+
+    /// Try to shrink the object to `new_size` bytes without moving it.
+    /// It succeeds if `new_size` is still in the size class that actually holds the object.
+    /// Objects in `linked_list_allocator` can't shrink in place.
+    /// After it succeeds, the object must be freed with `new_size`.
+    /// # Safety
+    /// Given pointer must be valid and allocated with `old_layout`.
+    #[cfg_attr(feature = "track_caller", track_caller)]
+    pub unsafe fn shrink_in_place(
+        &mut self,
+        ptr: *mut u8,
+        old_layout: Layout,
+        new_size: usize,
+    ) -> bool {
+        let shrunk = new_size <= old_layout.size() && self.fits_in_place(ptr, old_layout, new_size);
+        if shrunk {
+            self.resized_in_place(ptr, new_size);
+        }
+        shrunk
+    }
+
+    /// Update the side tables of the object at `ptr` resized to `new_size` bytes without moving it.
+    /// The call site of the resize replaces the one of the allocation like moving `reallocate`.
+    #[cfg_attr(feature = "track_caller", track_caller)]
+    #[cfg_attr(not(feature = "alloc-tags"), allow(unused_variables))]
+    fn resized_in_place(&mut self, ptr: *mut u8, new_size: usize) {
+        #[cfg(feature = "track_caller")]
+        self.callers.update(ptr, core::panic::Location::caller());
+        #[cfg(feature = "alloc-tags")]
+        self.tags.resize(ptr, new_size);
+    }
+
+    /// Return true if the object at `ptr` can be resized to `new_size` bytes without moving it.
+    /// The size class is looked up from the address instead of `old_layout`.
+    fn fits_in_place(&self, ptr: *mut u8, old_layout: Layout, new_size: usize) -> bool {
+        let Ok(new_layout) = Layout::from_size_align(new_size, old_layout.align()) else {
+            return false;
+        };
+
+        self.cache_index_of(ptr)
+            .is_some_and(|index| SlabAllocator::get_class_index(&new_layout) == Some(index))
+    }
+
+    /// Return the total bytes wasted by rounding up since the allocator was created.
+    #[must_use]
+    pub fn total_internal_fragmentation(&self) -> usize {
+        self.internal_fragmentation
+    }
+
+    /// Return the index of the cache whose region contains `ptr`.
+    /// `None` means that `ptr` is in the region of `linked_list_allocator` or out of the heap.
+    fn cache_index_of(&self, ptr: *mut u8) -> Option<usize> {
+        let offset = (ptr as usize).checked_sub(self.start_addr)?;
+        let index = offset / SlabAllocator::slab_allocated_size(self.heap_size);
+        (index < self.caches.len()).then_some(index)
+    }
+}
+
+impl<F: Fallback> Drop for SlabAllocator<F> {
+    /// Do nothing.
+    /// The allocator only borrows the heap region and doesn't own it,
+    /// so the region can be reused (e.g. by a new allocator) after it is dropped.
+    fn drop(&mut self) {}
+}
+
+/// Callbacks registered on `WildScreenAlloc`.
+/// They are stored in atomic pointers, so calling them takes no lock and they can allocate.
+struct Hooks {
+    /// Called when an allocation fails.
+    oom_handler: AtomicPtr<()>,
+    /// Called after an object is allocated.
+    on_alloc: AtomicPtr<()>,
+    /// Called after an object is deallocated.
+    on_dealloc: AtomicPtr<()>,
+}
+
+impl Hooks {
+    /// Return hooks that nothing is registered.
+    const fn new() -> Self {
+        Hooks {
+            oom_handler: AtomicPtr::new(core::ptr::null_mut()),
+            on_alloc: AtomicPtr::new(core::ptr::null_mut()),
+            on_dealloc: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    /// Return the registered handler of failed allocations.
+    fn oom_handler(&self) -> Option<fn(Layout)> {
+        let f = self.oom_handler.load(Ordering::Acquire);
+        // Safety: only `fn(Layout)` is stored in `oom_handler`.
+        (!f.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), fn(Layout)>(f) })
+    }
+
+    /// Return the registered hook of allocations.
+    fn on_alloc(&self) -> Option<fn(Layout, *mut u8)> {
+        let f = self.on_alloc.load(Ordering::Acquire);
+        // Safety: only `fn(Layout, *mut u8)` is stored in `on_alloc`.
+        (!f.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), fn(Layout, *mut u8)>(f) })
+    }
+
+    /// Return the registered hook of deallocations.
+    fn on_dealloc(&self) -> Option<fn(Layout, *mut u8)> {
+        let f = self.on_dealloc.load(Ordering::Acquire);
+        // Safety: only `fn(Layout, *mut u8)` is stored in `on_dealloc`.
+        (!f.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), fn(Layout, *mut u8)>(f) })
+    }
+}
+
+/// Global allocator that protects `SlabAllocator` by a lock.
+pub struct WildScreenAlloc {
+    allocator: Mutex<Option<SlabAllocator>>,
+    hooks: Hooks,
+    /// Counters published on each operation to be read without the lock.
+    counters: counters::Counters,
+    /// Number of following allocations that fail on purpose.
+    #[cfg(feature = "failpoints")]
+    failpoints: AtomicUsize,
+}
+
+impl WildScreenAlloc {
+    /// Return empty `WildScreenAlloc`.
+    /// This method exist for to initialize after heap address available.
+    /// ```no_run
+    /// use wild_screen_alloc::WildScreenAlloc;
+    ///
+    /// #[global_allocator]
+    /// static ALLOCATOR: WildScreenAlloc = WildScreenAlloc::empty();
+    ///
+    /// pub fn init_heap() { /* initialize ALLOCATOR */ }
+    /// ```
+    pub const fn empty() -> Self {
+        WildScreenAlloc {
+            allocator: Mutex::new(None),
+            hooks: Hooks::new(),
+            counters: counters::Counters::new(),
+            #[cfg(feature = "failpoints")]
+            failpoints: AtomicUsize::new(0),
+        }
+    }
+
+    /// Initialize allocator.
+    /// ```no_run
+    /// use wild_screen_alloc::WildScreenAlloc;
+    ///
+    /// #[global_allocator]
+    /// static ALLOCATOR: WildScreenAlloc = WildScreenAlloc::empty();
+    ///
+    /// pub fn init_heap() {
+    ///     let heap_start = 0x8020_0000;
+    ///     let heap_size = 0x20000;
+    ///     unsafe {
+    ///         ALLOCATOR.init(heap_start, heap_size);
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// The returned `HeapHandle` provides the management APIs without returning `Option`.
+    /// It only borrows the allocator, so `GlobalAlloc` can be used while it exists.
+    ///
+    /// # Safety
+    /// `start_addr` must be aligned to the page size.
+    pub unsafe fn init(&self, start_addr: usize, heap_size: usize) -> HeapHandle<'_> {
+        *self.allocator.lock() = Some(SlabAllocator::new(start_addr, heap_size));
+        self.counters.publish(&HeapSnapshot::default());
+        HeapHandle { alloc: self }
+    }
+
+    /// Return `HeapHandle` of the initialized allocator.
+    /// Return `None` if the allocator is not initialized.
+    pub fn handle(&self) -> Option<HeapHandle<'_>> {
+        if self.allocator.lock().is_none() {
+            return None;
+        }
+        Some(HeapHandle { alloc: self })
+    }
+
+    /// Create new allocator locked by mutex.
+    /// # Safety
+    /// `start_addr` must be aligned to the page size.
+    pub unsafe fn new(start_addr: usize, heap_size: usize) -> Self {
+        WildScreenAlloc {
+            allocator: Mutex::new(Some(SlabAllocator::new(start_addr, heap_size))),
+            hooks: Hooks::new(),
+            counters: counters::Counters::new(),
+            #[cfg(feature = "failpoints")]
+            failpoints: AtomicUsize::new(0),
+        }
+    }
+
+    /// Tear down the allocator and return to the state that `empty` starts in.
+    /// All outstanding allocations become invalid.
+    /// Allocating before `reinit` returns null, and deallocating does nothing.
+    /// Statistics are discarded with the allocator, but the registered hooks are kept.
+    /// An allocation running concurrently completes before it because both take the lock.
+    pub fn reset(&self) {
+        let mut allocator = self.allocator.lock();
+        *allocator = None;
+        self.counters.publish(&HeapSnapshot::default());
+    }
+
+    /// Initialize the allocator again (e.g. after `reset` on a soft restart).
+    /// The previous allocator is discarded if it exists.
+    /// # Safety
+    /// `start_addr` must be aligned to the page size.
+    /// All outstanding allocations become invalid.
+    pub unsafe fn reinit(&self, start_addr: usize, heap_size: usize) {
+        let mut allocator = self.allocator.lock();
+        *allocator = Some(SlabAllocator::new(start_addr, heap_size));
+        self.counters.publish(&HeapSnapshot::default());
+    }
+
+    /// Allocates `size` bytes aligned to `align` with the registered hooks.
+    /// Return null if `align` isn't a power of two, the layout is invalid or it fails.
+    /// The object must be freed by `deallocate_aligned` with the same `size` and `align`.
+    pub fn allocate_aligned(&self, size: usize, align: usize) -> *mut u8 {
+        match Layout::from_size_align(size, align) {
+            // Safety: `Layout::from_size_align` rejects invalid layouts.
+            Ok(layout) => unsafe { self.alloc(layout) },
+            Err(_) => core::ptr::null_mut(),
+        }
+    }
+
+    /// Deallocate object allocated by `allocate_aligned` with the registered hook.
+    /// # Safety
+    /// `ptr` must be allocated by `allocate_aligned` with the same `size` and `align`.
+    pub unsafe fn deallocate_aligned(&self, ptr: *mut u8, size: usize, align: usize) {
+        self.dealloc(ptr, Layout::from_size_align_unchecked(size, align));
+    }
+
+    /// Return the size of the heap in bytes like `linked_list_allocator::Heap::size`.
+    /// Return 0 if the allocator is not initialized.
+    pub fn size(&self) -> usize {
+        self.allocator
+            .lock()
+            .as_ref()
+            .map_or(0, SlabAllocator::heap_size)
+    }
+
+    /// Return bytes currently allocated like `linked_list_allocator::Heap::used`.
+    /// Slab objects are counted at their size class.
+    /// Return 0 if the allocator is not initialized.
+    pub fn used(&self) -> usize {
+        self.allocator
+            .lock()
+            .as_ref()
+            .map_or(0, SlabAllocator::used_bytes)
+    }
+
+    /// Return `size() - used()` like `linked_list_allocator::Heap::free`.
+    /// All of it can't always be allocated because it is split into the size classes.
+    /// Return 0 if the allocator is not initialized.
+    pub fn free(&self) -> usize {
+        self.allocator.lock().as_ref().map_or(0, |allocator| {
+            allocator.heap_size() - allocator.used_bytes()
+        })
+    }
+
+    /// Return the peak usage of the heap in bytes.
+    /// Return 0 if the allocator is not initialized.
+    pub fn peak_usage(&self) -> usize {
+        self.allocator
+            .lock()
+            .as_ref()
+            .map_or(0, SlabAllocator::peak_bytes)
+    }
+
+    /// Reset the peak usage to the current usage.
+    pub fn reset_peak(&self) {
+        if let Some(allocator) = self.allocator.lock().as_mut() {
+            allocator.reset_peak();
+            self.counters.publish(&allocator.snapshot());
+        }
+    }
+
+    /// Return statistics of the heap.
+    /// Return `None` if the allocator is not initialized.
+    pub fn stats(&self) -> Option<HeapStats> {
+        self.allocator.lock().as_ref().map(SlabAllocator::stats)
+    }
+
+    /// Return the counters of the heap without taking the lock (e.g. for a watchdog on another core).
+    /// Each field is up to date, but they may be slightly stale relative to each other
+    /// while an allocation is running, except that `frees` never exceeds `allocations`.
+    /// Use `snapshot_locked` for a consistent one.
+    /// All of them are zero if the allocator is not initialized.
+    pub fn snapshot(&self) -> HeapSnapshot {
+        self.counters.load()
+    }
+
+    /// Return the counters of the heap captured in a single lock.
+    /// Return `None` if the allocator is not initialized.
+    pub fn snapshot_locked(&self) -> Option<HeapSnapshot> {
+        self.allocator.lock().as_ref().map(SlabAllocator::snapshot)
+    }
+
+    /// Return a copy of the histogram of requested sizes.
+    /// Return `None` if the allocator is not initialized.
+    #[cfg(feature = "stats-histogram")]
+    pub fn histogram(&self) -> Option<Histogram> {
+        self.allocator
+            .lock()
+            .as_ref()
+            .map(|allocator| *allocator.histogram())
+    }
+
+    /// Attribute allocations in `f` to `tag` and restore the previous tag after it, even if `f` panics.
+    /// The tag is shared by all cores, so allocations on other cores in the meantime also get it.
+    /// `f` is called without tagging if the allocator is not initialized.
+    ///
+    /// # Panics
+    /// If `tag` isn't less than `NUM_OF_TAGS`, it will panic.
+    #[cfg(feature = "alloc-tags")]
+    pub fn with_tag<R>(&self, tag: u8, f: impl FnOnce() -> R) -> R {
+        /// Set the previous tag back when it is dropped.
+        struct RestoreTag<'a>(&'a WildScreenAlloc, Option<u8>);
+
+        impl Drop for RestoreTag<'_> {
+            fn drop(&mut self) {
+                if let (Some(previous), Some(allocator)) =
+                    (self.1, self.0.allocator.lock().as_mut())
+                {
+                    allocator.set_tag(previous);
+                }
+            }
+        }
+
+        let previous = self
+            .allocator
+            .lock()
+            .as_mut()
+            .map(|allocator| allocator.set_tag(tag));
+        let _restore = RestoreTag(self, previous);
+        f()
+    }
+
+    /// Make the next `n` allocations (including reallocations) fail as if the heap were exhausted.
+    /// The allocator isn't touched by the failed allocations, but the OOM handler is called.
+    /// `fail_next_allocations(0)` disables it.
+    #[cfg(feature = "failpoints")]
+    pub fn fail_next_allocations(&self, n: usize) {
+        self.failpoints.store(n, Ordering::Relaxed);
+    }
+
+    /// Return true if this allocation should fail on purpose.
+    /// It is called with the allocator lock held, so only `fail_next_allocations` can race with it.
+    /// Targets without read-modify-write instructions load and store the count instead.
+    #[cfg(feature = "failpoints")]
+    fn inject_failure(&self) -> bool {
+        #[cfg(target_has_atomic = "ptr")]
+        let injected = self
+            .failpoints
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok();
+        #[cfg(not(target_has_atomic = "ptr"))]
+        let injected = match self.failpoints.load(Ordering::Relaxed) {
+            0 => false,
+            n => {
+                self.failpoints.store(n - 1, Ordering::Relaxed);
+                true
+            }
+        };
+        injected
+    }
+
+    /// Return true if this allocation should fail on purpose.
+    /// It never fails without `failpoints` feature.
+    #[cfg(not(feature = "failpoints"))]
+    #[inline(always)]
+    fn inject_failure(&self) -> bool {
+        false
+    }
+
+    /// Return the sizes of all size classes in ascending order.
+    pub fn size_classes() -> &'static [usize] {
+        const SIZE_CLASSES: [usize; SlabSize::ALL.len()] = {
+            let mut sizes = [0; SlabSize::ALL.len()];
+            let mut index = 0;
+            while index < sizes.len() {
+                sizes[index] = SlabSize::ALL[index] as usize;
+                index += 1;
+            }
+            sizes
+        };
+
+        &SIZE_CLASSES
+    }
+
+    /// Return the size of the size class that `layout` will be allocated from.
+    /// `None` means that it is delegated to the fallback.
+    pub fn class_of(layout: Layout) -> Option<usize> {
+        SlabAllocator::size_class_for(&layout).map(usize::from)
+    }
+
+    /// Set the handler called with the failed layout when an allocation fails.
+    /// It is called outside the allocator lock, but it must not allocate because the heap is exhausted.
+    pub fn set_oom_handler(&self, f: fn(Layout)) {
+        self.hooks
+            .oom_handler
+            .store(f as *mut (), Ordering::Release);
+    }
+
+    /// Set the hook called with the layout and the address after each allocation.
+    /// It is called outside the allocator lock, so it may allocate as long as it doesn't recurse forever.
+    pub fn set_alloc_hook(&self, f: fn(Layout, *mut u8)) {
+        self.hooks.on_alloc.store(f as *mut (), Ordering::Release);
+    }
+
+    /// Set the hook called with the layout and the address after each deallocation.
+    /// It is called outside the allocator lock, so it may allocate as long as it doesn't recurse forever.
+    pub fn set_dealloc_hook(&self, f: fn(Layout, *mut u8)) {
+        self.hooks.on_dealloc.store(f as *mut (), Ordering::Release);
+    }
+}
+
+/// Handle of the initialized `WildScreenAlloc` returned by `WildScreenAlloc::init`.
+/// It shares the allocator with `GlobalAlloc`, so `reset` can still tear it down while the handle exists.
+/// Each method checks initialization once under the lock and panics if the allocator was reset.
+pub struct HeapHandle<'a> {
+    alloc: &'a WildScreenAlloc,
+}
+
+impl HeapHandle<'_> {
+    /// Run `f` with the locked allocator.
+    /// The counters are published after it.
+    ///
+    /// # Panics
+    /// If the allocator was reset after the handle was created, it will panic.
+    fn with<R>(&self, f: impl FnOnce(&mut SlabAllocator) -> R) -> R {
+        let mut allocator = self.alloc.allocator.lock();
+        let allocator = allocator
+            .as_mut()
+            .expect("The allocator was reset while HeapHandle exists");
+        let result = f(allocator);
+        self.alloc.counters.publish(&allocator.snapshot());
+        result
+    }
+
+    /// Return statistics of the heap.
+    pub fn stats(&self) -> HeapStats {
+        self.with(|allocator| allocator.stats())
+    }
+
+    /// Return the peak usage of the heap in bytes.
+    pub fn peak_usage(&self) -> usize {
+        self.with(|allocator| allocator.peak_bytes())
+    }
+
+    /// Reset the peak usage to the current usage.
+    pub fn reset_peak(&self) {
+        self.with(SlabAllocator::reset_peak);
+    }
+
+    /// Call `SlabAllocator::prealloc`.
+    pub fn prealloc(&self, layout: Layout, count: usize) -> Result<(), AllocError> {
+        self.with(|allocator| allocator.prealloc(layout, count))
+    }
+
+    /// Call `SlabAllocator::allocate_pages`.
+    pub fn allocate_pages(&self, n_pages: usize, align: usize) -> Option<NonNull<u8>> {
+        self.with(|allocator| allocator.allocate_pages(n_pages, align))
+    }
+
+    /// Call `SlabAllocator::deallocate_pages`.
+    /// # Safety
+    /// `ptr` must be allocated by `allocate_pages` with the same `n_pages` and `align`.
+    pub unsafe fn deallocate_pages(&self, ptr: NonNull<u8>, n_pages: usize, align: usize) {
+        self.with(|allocator| allocator.deallocate_pages(ptr, n_pages, align));
+    }
+}
+
+impl Default for WildScreenAlloc {
+    /// Same as `WildScreenAlloc::empty`.
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+unsafe impl GlobalAlloc for WildScreenAlloc {
+    /// Call `SlabAllocator::allocte` and the registered hooks.
+    /// Return null if the allocator is not initialized.
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = match *self.allocator.lock() {
+            Some(_) if self.inject_failure() => core::ptr::null_mut(),
+            Some(ref mut allocator) => {
+                let mark = counters::Mark::new(allocator);
+                let ptr = allocator.allocate(layout);
+                self.counters
+                    .update(&mark, allocator, ptr, core::ptr::null_mut());
+                ptr
+            }
+            None => core::ptr::null_mut(),
+        };
+
+        if ptr.is_null() {
+            if let Some(handler) = self.hooks.oom_handler() {
+                handler(layout);
+            }
+        } else if let Some(on_alloc) = self.hooks.on_alloc() {
+            on_alloc(layout, ptr);
+        }
+        ptr
+    }
+
+    /// Call `SlabAllocator::deallocate` and the registered hook.
+    /// Do nothing if the allocator is not initialized.
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match *self.allocator.lock() {
+            Some(ref mut allocator) => {
+                let mark = counters::Mark::new(allocator);
+                allocator.deallocate(ptr, layout);
+                self.counters
+                    .update(&mark, allocator, core::ptr::null_mut(), ptr);
+            }
+            None => return,
+        }
+
+        if let Some(on_dealloc) = self.hooks.on_dealloc() {
+            on_dealloc(layout, ptr);
+        }
+    }
+
+    /// Call `SlabAllocator::reallocate` and the registered hooks.
+    /// Return null if the allocator is not initialized.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = match *self.allocator.lock() {
+            Some(_) if self.inject_failure() => core::ptr::null_mut(),
+            Some(ref mut allocator) => {
+                let mark = counters::Mark::new(allocator);
+                let new_ptr = allocator.reallocate(ptr, layout, new_size);
+                self.counters.update(&mark, allocator, new_ptr, ptr);
+                new_ptr
+            }
+            None => core::ptr::null_mut(),
+        };
+
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        if new_ptr.is_null() {
+            if let Some(handler) = self.hooks.oom_handler() {
+                handler(new_layout);
+            }
+        } else {
+            if let Some(on_dealloc) = self.hooks.on_dealloc() {
+                on_dealloc(layout, ptr);
+            }
+            if let Some(on_alloc) = self.hooks.on_alloc() {
+                on_alloc(new_layout, new_ptr);
+            }
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod alloc_tests {
+    use crate::{constants, HeapSnapshot, SlabAllocator, SlabSize, WildScreenAlloc};
+    use alloc::alloc::{GlobalAlloc, Layout};
+    use core::mem::{align_of, size_of};
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    const HEAP_SIZE: usize = 32 * constants::PAGE_SIZE;
+    #[cfg_attr(not(feature = "page_size_16k"), repr(align(4096)))]
+    #[cfg_attr(feature = "page_size_16k", repr(align(16384)))]
+    struct DummyHeap {
+        heap_space: [u8; HEAP_SIZE],
+    }
+
+    #[test]
+    fn create_allocator() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+
+        unsafe {
+            let _ = SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+        }
+    }
+
+    #[test]
+    fn heap_extent() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let start_addr = &dummy_heap.heap_space as *const u8 as usize;
+
+        unsafe {
+            let allocator = SlabAllocator::new(start_addr, HEAP_SIZE);
+            assert_eq!(allocator.start_addr(), start_addr);
+            assert_eq!(allocator.heap_size(), HEAP_SIZE);
+            assert_eq!(allocator.region(), start_addr..start_addr + HEAP_SIZE);
+        }
+    }
+
+    #[test]
+    fn reuse_region_after_drop() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let start_addr = &dummy_heap.heap_space as *const u8 as usize;
+        let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+        let mut allocated = [core::ptr::null_mut(); 8];
+
+        unsafe {
+            let mut allocator = SlabAllocator::new(start_addr, HEAP_SIZE);
+            assert_eq!(allocator.allocate_batch(layout, 8, &mut allocated), 8);
+            drop(allocator);
+
+            let mut allocator = SlabAllocator::new(start_addr, HEAP_SIZE);
+            assert_eq!(allocator.used_bytes(), 0);
+            assert_eq!(allocator.allocate_batch(layout, 8, &mut allocated), 8);
+            allocator.forget_region();
+
+            let mut allocator = SlabAllocator::new(start_addr, HEAP_SIZE);
+            assert_eq!(allocator.used_bytes(), 0);
+            let ptr = allocator.allocate(layout);
+            assert_eq!(ptr, allocated[0]);
+            ptr.write_bytes(0xff, layout.size());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "page_size_16k")]
+    #[should_panic(expected = "Start address should be page aligned")]
+    fn create_allocator_unaligned_to_16k_page() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+
+        unsafe {
+            let _ = SlabAllocator::new(
+                &dummy_heap.heap_space as *const u8 as usize + 4096,
+                HEAP_SIZE - 4096,
+            );
+        }
+    }
+
+    #[test]
+    fn alloc_and_free_test() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let size = size_of::<usize>() * 2;
+        let layout = Layout::from_size_align(size, align_of::<usize>());
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let addr = allocator.allocate(layout.clone().unwrap());
+            assert!(!addr.is_null());
+
+            allocator.deallocate(addr, layout.unwrap());
+        }
+    }
+
+    #[test]
+    fn alloc_4096_bytes() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let size = 4096;
+        let layout = Layout::from_size_align(size, align_of::<usize>());
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let addr = allocator.allocate(layout.clone().unwrap());
+            assert!(!addr.is_null());
+
+            allocator.deallocate(addr, layout.unwrap());
+        }
+    }
+
+    #[test]
+    fn alloc_4104_bytes() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let size = 4104;
+        let layout = Layout::from_size_align(size, align_of::<usize>());
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let addr = allocator.allocate(layout.clone().unwrap());
+            assert!(!addr.is_null());
+
+            allocator.deallocate(addr, layout.unwrap());
+        }
+    }
+
+    #[test]
+    fn alloc_8096_bytes() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let size = 8096;
+        let layout = Layout::from_size_align(size, align_of::<usize>());
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let addr = allocator.allocate(layout.clone().unwrap());
+            assert!(!addr.is_null());
+
+            allocator.deallocate(addr, layout.unwrap());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Heap must be at least 16 pages")]
+    fn create_allocator_smaller_than_16_pages() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+
+        unsafe {
+            let _ = SlabAllocator::new(
+                &dummy_heap.heap_space as *const u8 as usize,
+                8 * constants::PAGE_SIZE,
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    #[should_panic(expected = "Layout doesn't match the size class of the object")]
+    fn deallocate_with_wrong_layout() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+        let wrong_layout = Layout::from_size_align(128, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let ptr = allocator.allocate(layout);
+            allocator.deallocate(ptr, wrong_layout);
+        }
+    }
+
+    #[test]
+    fn deallocate_null() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            allocator.deallocate(core::ptr::null_mut(), layout);
+            assert_eq!(allocator.snapshot(), HeapSnapshot::default());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    #[should_panic(expected = "Pointer is not owned by the allocator")]
+    fn deallocate_foreign_pointer() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+        let mut foreign = [0_u8; 64];
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            allocator.deallocate(foreign.as_mut_ptr(), layout);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "debug_checks"))]
+    fn deallocate_foreign_pointer() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+        let mut foreign = [0_u8; 64];
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let ptr = allocator.allocate(layout);
+            let used_bytes = allocator.used_bytes();
+            allocator.deallocate(foreign.as_mut_ptr(), layout);
+            assert_eq!(allocator.used_bytes(), used_bytes);
+
+            // the slab is not corrupted.
+            allocator.deallocate(ptr, layout);
+            assert_eq!(allocator.allocate(layout), ptr);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "track_caller")]
+    fn dump_leaks() {
+        use alloc::string::String;
+
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let first_line = line!() + 1;
+            let leaked = allocator.allocate(layout);
+            let second_line = line!() + 1;
+            let _ = allocator.allocate(layout);
+            let freed = allocator.allocate(layout);
+            allocator.deallocate(freed, layout);
+
+            let mut dump = String::new();
+            allocator.dump_leaks(&mut dump).unwrap();
+            assert_eq!(dump.lines().count(), 2);
+            assert!(dump.contains(&alloc::format!("{leaked:p}")));
+            assert!(dump.contains(&alloc::format!("{}:{first_line}:", file!())));
+            assert!(dump.contains(&alloc::format!("{}:{second_line}:", file!())));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "track_caller")]
+    fn dump_leaks_after_resize_in_place() {
+        use alloc::string::String;
+
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(40, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let ptr = allocator.allocate(layout);
+            let resized_line = line!() + 1;
+            assert_eq!(allocator.reallocate(ptr, layout, 60), ptr);
+
+            let mut dump = String::new();
+            allocator.dump_leaks(&mut dump).unwrap();
+            assert_eq!(dump.lines().count(), 1);
+            assert!(dump.contains(&alloc::format!("{}:{resized_line}:", file!())));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "track_caller")]
+    fn dump_leaks_through_global_alloc() {
+        use alloc::string::String;
+
+        static ALLOCATOR: WildScreenAlloc = WildScreenAlloc::empty();
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+
+        unsafe {
+            ALLOCATOR.reinit(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let alloc_line = line!() + 1;
+            let ptr = ALLOCATOR.alloc(layout);
+
+            let mut dump = String::new();
+            ALLOCATOR
+                .allocator
+                .lock()
+                .as_ref()
+                .unwrap()
+                .dump_leaks(&mut dump)
+                .unwrap();
+            // `GlobalAlloc::alloc` can't be `#[track_caller]`, so the call inside it is recorded.
+            assert_eq!(dump.lines().count(), 1);
+            assert!(dump.contains(&alloc::format!("{}:", file!())));
+            assert!(!dump.contains(&alloc::format!("{}:{alloc_line}:", file!())));
+
+            ALLOCATOR.dealloc(ptr, layout);
+            ALLOCATOR.reset();
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "event_log")]
+    fn recent_events() {
+        use crate::event::CAPACITY;
+        use crate::{Event, EventKind};
+
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(32, align_of::<usize>()).unwrap();
+        let mut allocated = [core::ptr::null_mut(); CAPACITY];
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            assert_eq!(allocator.recent_events().count(), 0);
+
+            let count = allocator.allocate_batch(layout, CAPACITY, &mut allocated);
+            assert_eq!(count, CAPACITY);
+            for ptr in &allocated[..CAPACITY / 2] {
+                allocator.deallocate(*ptr, layout);
+            }
+
+            let expected = allocated[CAPACITY / 2..]
+                .iter()
+                .map(|ptr| (EventKind::Alloc, *ptr))
+                .chain(
+                    allocated[..CAPACITY / 2]
+                        .iter()
+                        .map(|ptr| (EventKind::Dealloc, *ptr)),
+                );
+            assert_eq!(allocator.recent_events().count(), CAPACITY);
+            for (event, (kind, ptr)) in allocator.recent_events().zip(expected) {
+                assert_eq!(
+                    event,
+                    Event {
+                        kind,
+                        size_class: Some(SlabSize::Slab32Bytes),
+                        addr: ptr as usize,
+                    }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn send_and_sync() {
+        fn assert_send<T: Send>() {}
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send::<SlabAllocator>();
+        assert_send_sync::<WildScreenAlloc>();
+    }
+
+    #[test]
+    fn reset_and_reinit() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let start_addr = &dummy_heap.heap_space as *const u8 as usize;
+        let layout = Layout::from_size_align(4096, align_of::<usize>()).unwrap();
+        let allocate_all = |allocator: &WildScreenAlloc| {
+            core::iter::from_fn(|| Some(unsafe { allocator.alloc(layout) }))
+                .take_while(|ptr| !ptr.is_null())
+                .count()
+        };
+
+        unsafe {
+            let allocator = WildScreenAlloc::new(start_addr, HEAP_SIZE);
+            let capacity = allocate_all(&allocator);
+            assert!(capacity > 0);
+
+            let ptr = allocator.alloc(layout);
+            allocator.reset();
+            assert!(allocator.allocator.lock().is_none());
+            assert!(allocator.alloc(layout).is_null());
+            assert!(allocator.realloc(ptr, layout, 2 * layout.size()).is_null());
+            allocator.dealloc(ptr, layout);
+            allocator.reinit(start_addr, HEAP_SIZE);
+            assert_eq!(allocate_all(&allocator), capacity);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "failpoints")]
+    fn fail_next_allocations() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let allocator =
+                WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let ptr = allocator.alloc(layout);
+            assert!(!ptr.is_null());
+
+            allocator.fail_next_allocations(2);
+            assert!(allocator.alloc(layout).is_null());
+            assert!(allocator.realloc(ptr, layout, 128).is_null());
+            // the failed allocations didn't touch the allocator.
+            let used_bytes = allocator.allocator.lock().as_ref().unwrap().used_bytes();
+            assert_eq!(used_bytes, 64);
+
+            let next = allocator.alloc(layout);
+            assert!(!next.is_null());
+
+            allocator.fail_next_allocations(usize::MAX);
+            assert!(allocator.alloc(layout).is_null());
+            allocator.fail_next_allocations(0);
+            assert!(!allocator.alloc(layout).is_null());
+        }
+    }
+
+    #[test]
+    fn peak_usage() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let small = Layout::from_size_align(50, align_of::<usize>()).unwrap();
+        let large = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let allocator =
+                WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            assert_eq!(allocator.peak_usage(), 0);
+
+            // 3 objects of 64 bytes class and 5000 bytes at most at the same time.
+            let first = [allocator.alloc(small), allocator.alloc(small)];
+            let big = allocator.alloc(large);
+            allocator.dealloc(first[0], small);
+            let second = allocator.alloc(small);
+            let third = allocator.alloc(small);
+            allocator.dealloc(big, large);
+            for ptr in [first[1], second, third] {
+                allocator.dealloc(ptr, small);
+            }
+
+            let stats = allocator.stats().unwrap();
+            assert_eq!(stats.used_bytes, 0);
+            assert_eq!(stats.peak_bytes, 3 * 64 + 5000);
+            assert_eq!(allocator.peak_usage(), 3 * 64 + 5000);
+
+            allocator.reset_peak();
+            assert_eq!(allocator.peak_usage(), 0);
+            let ptr = allocator.alloc(small);
+            assert_eq!(allocator.peak_usage(), 64);
+            allocator.dealloc(ptr, small);
+            assert_eq!(allocator.peak_usage(), 64);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "stats-histogram")]
+    fn size_histogram() {
+        use crate::Histogram;
+
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+
+        unsafe {
+            let allocator =
+                WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            for size in [1, 8, 9, 40, 40, 60, 64, 100, 5000] {
+                let layout = Layout::from_size_align(size, 1).unwrap();
+                assert!(!allocator.alloc(layout).is_null());
+            }
+
+            let histogram = allocator.histogram().unwrap();
+            assert_eq!(histogram.buckets[0], 2); // 1, 8
+            assert_eq!(histogram.buckets[1], 1); // 9
+            assert_eq!(histogram.buckets[4], 2); // 40, 40
+            assert_eq!(histogram.buckets[7], 2); // 60, 64
+            assert_eq!(histogram.buckets[12], 1); // 100
+            assert_eq!(histogram.buckets.iter().sum::<usize>(), 8);
+            assert_eq!(histogram.oversized, 1);
+            assert_eq!(histogram.class_count(SlabSize::Slab8Bytes), 2);
+            assert_eq!(histogram.class_count(SlabSize::Slab64Bytes), 4);
+            assert_eq!(histogram.class_count(SlabSize::Slab128Bytes), 1);
+            assert_eq!(Histogram::bucket_range(12), 97..=104);
+            // 7 + 0 + 7 + 24 + 24 + 4 + 0 + 28
+            assert_eq!(histogram.wasted_bytes, 94);
+        }
+    }
+
+    #[test]
+    fn default_allocator() {
+        const _: WildScreenAlloc = WildScreenAlloc::empty();
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(size_of::<usize>(), align_of::<usize>()).unwrap();
+
+        unsafe {
+            let allocator = WildScreenAlloc::default();
+            allocator.init(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let addr = allocator.alloc(layout);
+            assert!(!addr.is_null());
+
+            allocator.dealloc(addr, layout);
+        }
+    }
+
+    #[test]
+    fn heap_handle() {
+        let allocator = WildScreenAlloc::empty();
+        let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+        assert!(allocator.handle().is_none());
+
+        // the runtime checks still behave when uninitialized.
+        assert_eq!(allocator.stats(), None);
+        assert_eq!(allocator.peak_usage(), 0);
+
+        let heap_size = constants::NUM_OF_SLABS * 8 * constants::PAGE_SIZE;
+        let heap_layout = Layout::from_size_align(heap_size, constants::PAGE_SIZE).unwrap();
+
+        unsafe {
+            let heap = alloc::alloc::alloc(heap_layout);
+            let handle = allocator.init(heap as usize, heap_size);
+            assert_eq!(handle.stats().used_bytes, 0);
+            assert_eq!(handle.prealloc(layout, 10), Ok(()));
+
+            let pages = handle.allocate_pages(2, constants::PAGE_SIZE).unwrap();
+            assert_eq!(handle.peak_usage(), 2 * constants::PAGE_SIZE);
+            handle.deallocate_pages(pages, 2, constants::PAGE_SIZE);
+            handle.reset_peak();
+            assert_eq!(handle.peak_usage(), 0);
+
+            // the global path can allocate while the handle is alive.
+            let ptr = allocator.alloc(layout);
+            assert!(!ptr.is_null());
+            assert_eq!(handle.stats().used_bytes, 64);
+            allocator.dealloc(ptr, layout);
+            assert!(allocator.handle().is_some());
+
+            alloc::alloc::dealloc(heap, heap_layout);
+        }
+    }
+
+    #[test]
+    fn size_class_for_layout() {
+        let layout = Layout::from_size_align(100, align_of::<usize>()).unwrap();
+        assert_eq!(
+            SlabAllocator::size_class_for(&layout),
+            Some(SlabSize::Slab128Bytes)
+        );
+
+        // over-aligned layout is bumped to the aligned class.
+        let layout = Layout::from_size_align(100, 256).unwrap();
+        assert_eq!(
+            SlabAllocator::size_class_for(&layout),
+            Some(SlabSize::Slab256Bytes)
+        );
+
+        let layout = Layout::from_size_align(4097, align_of::<usize>()).unwrap();
+        assert_eq!(SlabAllocator::size_class_for(&layout), None);
+    }
+
+    #[test]
+    fn internal_fragmentation() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let small = Layout::from_size_align(65, align_of::<usize>()).unwrap();
+        let exact = Layout::from_size_align(256, align_of::<usize>()).unwrap();
+        let large = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+        assert_eq!(SlabAllocator::waste_for(&small), 31);
+        assert_eq!(SlabAllocator::waste_for(&exact), 0);
+        assert_eq!(SlabAllocator::waste_for(&large), 0);
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let mut addr = core::ptr::null_mut();
+            for layout in [small, exact, large, small] {
+                addr = allocator.allocate(layout);
+                assert!(!addr.is_null());
+            }
+            assert_eq!(allocator.total_internal_fragmentation(), 62);
+
+            // freeing doesn't decrease the total
+            allocator.deallocate(addr, small);
+            assert_eq!(allocator.total_internal_fragmentation(), 62);
+            assert_eq!(allocator.stats().total_internal_fragmentation, 62);
+        }
+    }
+
+    #[test]
+    fn oom_handler() {
+        static OOM_OCCURRED: AtomicBool = AtomicBool::new(false);
+        fn handler(_layout: Layout) {
+            OOM_OCCURRED.store(true, Ordering::Relaxed);
+        }
+
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(HEAP_SIZE, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let allocator =
+                WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            allocator.set_oom_handler(handler);
+            assert!(allocator.alloc(layout).is_null());
+        }
+        assert!(OOM_OCCURRED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn alloc_and_dealloc_hooks() {
+        const RING_SIZE: usize = 8;
+        /// (is_alloc, address)
+        static EVENTS: crate::lock::Mutex<([(bool, usize); RING_SIZE], usize)> =
+            crate::lock::Mutex::new(([(false, 0); RING_SIZE], 0));
+        fn record(is_alloc: bool, ptr: *mut u8) {
+            let mut events = EVENTS.lock();
+            let index = events.1 % RING_SIZE;
+            events.0[index] = (is_alloc, ptr as usize);
+            events.1 += 1;
+        }
+
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(size_of::<usize>(), align_of::<usize>()).unwrap();
+
+        unsafe {
+            let allocator =
+                WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            allocator.set_alloc_hook(|_, ptr| record(true, ptr));
+            allocator.set_dealloc_hook(|_, ptr| record(false, ptr));
+
+            let first = allocator.alloc(layout);
+            let second = allocator.alloc(layout);
+            allocator.dealloc(first, layout);
+            allocator.dealloc(second, layout);
+
+            let events = EVENTS.lock();
+            assert_eq!(events.1, 4);
+            assert_eq!(
+                events.0[..4],
+                [
+                    (true, first as usize),
+                    (true, second as usize),
+                    (false, first as usize),
+                    (false, second as usize),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn hook_that_allocates() {
+        static ALLOCATOR: WildScreenAlloc = WildScreenAlloc::empty();
+        static IN_HOOK: AtomicBool = AtomicBool::new(false);
+        static NESTED: AtomicBool = AtomicBool::new(false);
+        fn on_alloc(layout: Layout, _: *mut u8) {
+            if IN_HOOK.swap(true, Ordering::Relaxed) {
+                return;
+            }
+            unsafe {
+                let ptr = ALLOCATOR.alloc(layout);
+                assert!(!ptr.is_null());
+                ALLOCATOR.dealloc(ptr, layout);
+            }
+            NESTED.store(true, Ordering::Relaxed);
+            IN_HOOK.store(false, Ordering::Relaxed);
+        }
+
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(size_of::<usize>(), align_of::<usize>()).unwrap();
+
+        unsafe {
+            ALLOCATOR.reinit(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            ALLOCATOR.set_alloc_hook(on_alloc);
+
+            let ptr = ALLOCATOR.alloc(layout);
+            assert!(!ptr.is_null());
+            assert!(NESTED.load(Ordering::Relaxed));
+            ALLOCATOR.dealloc(ptr, layout);
+            ALLOCATOR.reset();
+        }
+    }
+
+    #[test]
+    fn slab_size_conversion() {
+        assert_eq!(SlabSize::try_from(0), Ok(SlabSize::Slab8Bytes));
+        assert_eq!(SlabSize::try_from(9), Ok(SlabSize::Slab16Bytes));
+        assert_eq!(SlabSize::try_from(33), Ok(SlabSize::Slab64Bytes));
+        assert_eq!(SlabSize::try_from(64), Ok(SlabSize::Slab64Bytes));
+        assert_eq!(SlabSize::try_from(65), Ok(SlabSize::Slab96Bytes));
+        assert_eq!(SlabSize::try_from(97), Ok(SlabSize::Slab128Bytes));
+        assert_eq!(SlabSize::try_from(1500), Ok(SlabSize::Slab1536Bytes));
+        assert_eq!(SlabSize::try_from(2048), Ok(SlabSize::Slab2048Bytes));
+        assert_eq!(SlabSize::try_from(4096), Ok(SlabSize::Slab4096Bytes));
+        assert_eq!(SlabSize::try_from(4097), Err(()));
+
+        assert!(SlabSize::Slab8Bytes < SlabSize::Slab16Bytes);
+        assert!(SlabSize::Slab64Bytes < SlabSize::Slab128Bytes);
+        assert!(SlabSize::Slab4096Bytes > SlabSize::Slab2048Bytes);
+    }
+
+    #[test]
+    fn reset_allocator() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            for size in [8, 100, 1000, 4096, 5000] {
+                let layout = Layout::from_size_align(size, align_of::<usize>()).unwrap();
+                assert!(!allocator.allocate(layout).is_null());
+            }
+            assert!(allocator.used_bytes() > 0);
+
+            allocator.reset();
+            assert_eq!(allocator.used_bytes(), 0);
+
+            let layout = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+            let addr = allocator.allocate(layout);
+            assert!(!addr.is_null());
+            allocator.deallocate(addr, layout);
+        }
+    }
+
+    #[test]
+    fn allocate_batch() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(1024, align_of::<usize>()).unwrap();
+        let capacity =
+            crate::align::align_down(HEAP_SIZE / constants::NUM_OF_SLABS, constants::PAGE_SIZE)
+                / 1024;
+        let mut out = [core::ptr::null_mut(); 64];
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            // objects over the capacity of the class are allocated from `linked_list_allocator`.
+            let count = allocator.allocate_batch(layout, capacity + 4, &mut out);
+            assert_eq!(count, capacity + 4);
+            let fallback_bottom = allocator.fallback_region().start as *mut u8;
+            assert!(out[..capacity].iter().all(|ptr| *ptr < fallback_bottom));
+            assert!(out[capacity..count]
+                .iter()
+                .all(|ptr| *ptr >= fallback_bottom));
+
+            let allocated = &out[..count];
+            for (i, ptr) in allocated.iter().enumerate() {
+                assert!(!ptr.is_null());
+                assert!(!allocated[i + 1..].contains(ptr));
+                ptr.write_bytes(0xff, 1024);
+            }
+            for ptr in allocated {
+                allocator.deallocate(*ptr, layout);
+            }
+            let snapshot = allocator.snapshot();
+            assert_eq!(snapshot.allocations, count);
+            assert_eq!(snapshot.frees, count);
+        }
+    }
+
+    #[test]
+    fn alloc_page_sized_objects() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let mut allocated = [core::ptr::null_mut(); 16];
+
+        // heap size that isn't a multiple of `NUM_OF_SLABS` pages.
+        for heap_size in [HEAP_SIZE, HEAP_SIZE - constants::PAGE_SIZE] {
+            for size in [3000, 4096] {
+                let layout = Layout::from_size_align(size, align_of::<usize>()).unwrap();
+                unsafe {
+                    let mut allocator =
+                        SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, heap_size);
+                    let count = allocator.allocate_batch(layout, allocated.len(), &mut allocated);
+                    assert!(count > 0);
+
+                    let allocated = &mut allocated[..count];
+                    allocated.sort();
+                    for pair in allocated.windows(2) {
+                        assert!(pair[0] as usize + 4096 <= pair[1] as usize);
+                    }
+                    for ptr in allocated.iter() {
+                        assert!(crate::align::is_aligned(*ptr as usize, 4096));
+                        ptr.write_bytes(0xff, size);
+                        allocator.deallocate(*ptr, layout);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn heap_size_with_sub_page_tail() {
+        let heap_size = 0x10_0000 + 6000;
+        let heap_layout = Layout::from_size_align(heap_size, constants::PAGE_SIZE).unwrap();
+
+        unsafe {
+            let heap = alloc::alloc::alloc(heap_layout);
+            let mut allocator = SlabAllocator::new(heap as usize, heap_size);
+
+            // no bytes are lost: the tail belongs to `linked_list_allocator`.
+            let fallback_bottom = allocator.fallback_region().start as *mut u8;
+            assert!(crate::align::is_aligned(
+                fallback_bottom as usize,
+                constants::PAGE_SIZE
+            ));
+            assert_eq!(allocator.fallback_region().end, heap as usize + heap_size);
+
+            let layout = Layout::from_size_align(4096, 4096).unwrap();
+            let capacity = SlabAllocator::slab_allocated_size(heap_size) / 4096;
+            let mut allocated = [core::ptr::null_mut(); 64];
+            let count = allocator.allocate_batch(layout, capacity, &mut allocated);
+            assert_eq!(count, capacity);
+            for ptr in &allocated[..count] {
+                assert!(crate::align::is_aligned(*ptr as usize, 4096));
+                assert!(*ptr < fallback_bottom);
+            }
+
+            // the whole fallback region including the tail can be allocated at once.
+            let fallback_size = allocator.fallback_free_bytes();
+            let layout = Layout::from_size_align(fallback_size, align_of::<usize>()).unwrap();
+            let ptr = allocator.allocate(layout);
+            assert!(!ptr.is_null());
+            ptr.write_bytes(0xff, fallback_size);
+
+            alloc::alloc::dealloc(heap, heap_layout);
+        }
+    }
+
+    #[test]
+    fn spill_exhausted_class_to_fallback() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(2048, align_of::<usize>()).unwrap();
+        let capacity = SlabAllocator::slab_allocated_size(HEAP_SIZE) / 2048;
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let fallback_bottom = allocator.fallback_region().start as *mut u8;
+            for _ in 0..capacity {
+                assert!(allocator.allocate(layout) < fallback_bottom);
+            }
+
+            let spilled = allocator.allocate(layout);
+            assert!(spilled >= fallback_bottom);
+            assert!(crate::align::is_aligned(spilled as usize, 2048));
+            assert_eq!(allocator.fallback_used_bytes(), 2048);
+
+            allocator.deallocate(spilled, layout);
+            assert_eq!(allocator.fallback_used_bytes(), 0);
+        }
+    }
+
+    #[test]
+    fn allocate_from_large_fallback() {
+        // `linked_list_allocator` gets a little over 3 MiB.
+        let heap_size = (3 * 0x10_0000 + constants::PAGE_SIZE) * constants::NUM_OF_SLABS;
+        let heap_layout = Layout::from_size_align(heap_size, constants::PAGE_SIZE).unwrap();
+        let layout = Layout::from_size_align(3 * 0x10_0000, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let heap = alloc::alloc::alloc(heap_layout);
+            let mut allocator = SlabAllocator::new(heap as usize, heap_size);
+
+            let ptr = allocator.allocate(layout);
+            assert!(!ptr.is_null());
+            assert!(ptr >= allocator.fallback_region().start as *mut u8);
+            assert!(ptr as usize + layout.size() <= allocator.fallback_region().end);
+            ptr.write_bytes(0xff, layout.size());
+
+            allocator.deallocate(ptr, layout);
+            assert_eq!(allocator.used_bytes(), 0);
+            alloc::alloc::dealloc(heap, heap_layout);
+        }
+    }
+
+    #[test]
+    fn allocate_pages() {
+        // `linked_list_allocator` gets 32 pages.
+        let heap_size = constants::NUM_OF_SLABS * 32 * constants::PAGE_SIZE;
+        let heap_layout = Layout::from_size_align(heap_size, constants::PAGE_SIZE).unwrap();
+
+        unsafe {
+            let heap = alloc::alloc::alloc(heap_layout);
+            let mut allocator = SlabAllocator::new(heap as usize, heap_size);
+            let free = allocator.fallback_free_bytes();
+
+            let mut allocated = [None; 4];
+            let requests = [(3, 1), (5, 1), (7, 1), (3, 4 * constants::PAGE_SIZE)];
+            for (slot, (n_pages, align)) in allocated.iter_mut().zip(requests) {
+                let ptr = allocator.allocate_pages(n_pages, align).unwrap();
+                assert!(crate::align::is_aligned(
+                    ptr.as_ptr() as usize,
+                    align.max(constants::PAGE_SIZE)
+                ));
+                ptr.as_ptr()
+                    .write_bytes(0xff, n_pages * constants::PAGE_SIZE);
+                *slot = Some((ptr, n_pages, align));
+            }
+            let mut ranges = allocated.map(|slot| {
+                let (ptr, n_pages, _) = slot.unwrap();
+                let start = ptr.as_ptr() as usize;
+                start..start + n_pages * constants::PAGE_SIZE
+            });
+            ranges.sort_by_key(|range| range.start);
+            assert!(ranges.windows(2).all(|pair| pair[0].end <= pair[1].start));
+
+            for (ptr, n_pages, align) in allocated.into_iter().flatten() {
+                allocator.deallocate_pages(ptr, n_pages, align);
+            }
+            assert_eq!(allocator.fallback_free_bytes(), free);
+            assert!(allocator
+                .allocate_pages(free / constants::PAGE_SIZE, constants::PAGE_SIZE)
+                .is_some());
+            assert!(allocator.allocate_pages(1, 3).is_none());
+
+            alloc::alloc::dealloc(heap, heap_layout);
+        }
+    }
+
+    #[test]
+    fn allocate_aligned() {
+        // `linked_list_allocator` gets a little over 2 MiB.
+        let heap_size = (2 * 0x10_0000 + constants::PAGE_SIZE) * constants::NUM_OF_SLABS;
+        let heap_layout = Layout::from_size_align(heap_size, constants::PAGE_SIZE).unwrap();
+
+        unsafe {
+            let heap = alloc::alloc::alloc(heap_layout);
+            let mut allocator = SlabAllocator::new(heap as usize, heap_size);
+
+            for align in (3..=20).map(|order| 1 << order) {
+                for size in [1, 100, 4096, 5000] {
+                    let ptr = allocator.allocate_aligned(size, align);
+                    assert!(!ptr.is_null(), "size: {size}, align: {align}");
+                    assert!(crate::align::is_aligned(ptr as usize, align));
+                    ptr.write_bytes(0xff, size);
+                    allocator.deallocate_aligned(ptr, size, align);
+                }
+            }
+            assert_eq!(allocator.used_bytes(), 0);
+
+            // invalid or impossible alignment
+            assert!(allocator.allocate_aligned(64, 24).is_null());
+            assert!(allocator.allocate_aligned(64, 0).is_null());
+            assert!(allocator.allocate_aligned(64, 1 << 30).is_null());
+
+            alloc::alloc::dealloc(heap, heap_layout);
+        }
+    }
+
+    #[test]
+    fn resize_in_place() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let small = Layout::from_size_align(100, align_of::<usize>()).unwrap();
+        let large = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+
+            // 100 bytes are in the 128 bytes class.
+            let ptr = allocator.allocate(small);
+            assert!(allocator.grow_in_place(ptr, small, 128));
+            assert!(!allocator.grow_in_place(ptr, small, 129));
+            assert!(!allocator.grow_in_place(ptr, small, 64));
+            assert!(allocator.shrink_in_place(ptr, small, 97));
+            assert!(!allocator.shrink_in_place(ptr, small, 96));
+            assert!(!allocator.shrink_in_place(ptr, small, 128));
+
+            // the class is looked up from the address, not from the layout.
+            let exact = Layout::from_size_align(128, align_of::<usize>()).unwrap();
+            allocator.deallocate(ptr, exact);
+
+            // objects in `linked_list_allocator` can't be resized in place.
+            let ptr = allocator.allocate(large);
+            assert!(!allocator.grow_in_place(ptr, large, 6000));
+            assert!(!allocator.shrink_in_place(ptr, large, 4500));
+            assert!(!allocator.shrink_in_place(ptr, large, 100));
+            allocator.deallocate(ptr, large);
+
+            assert_eq!(allocator.used_bytes(), 0);
+        }
+    }
+
+    #[test]
+    fn tiny_size_classes() {
+        const NUM_OF_OBJECTS: usize = 1000;
+        /// Return the number of pages that `objects` spread over.
+        fn pages_of(objects: &[*mut u8; NUM_OF_OBJECTS]) -> usize {
+            let mut pages = objects.map(|ptr| ptr as usize / constants::PAGE_SIZE);
+            pages.sort_unstable();
+            1 + pages.windows(2).filter(|pair| pair[0] != pair[1]).count()
+        }
+
+        let heap_size = constants::NUM_OF_SLABS * 16 * constants::PAGE_SIZE;
+        let heap_layout = Layout::from_size_align(heap_size, constants::PAGE_SIZE).unwrap();
+        let mut allocated = [core::ptr::null_mut(); NUM_OF_OBJECTS];
+
+        unsafe {
+            let heap = alloc::alloc::alloc(heap_layout);
+            let mut allocator = SlabAllocator::new(heap as usize, heap_size);
+
+            let layout = Layout::from_size_align(16, align_of::<usize>()).unwrap();
+            let count = allocator.allocate_batch(layout, NUM_OF_OBJECTS, &mut allocated);
+            assert_eq!(count, NUM_OF_OBJECTS);
+            let tiny_used_bytes = allocator.used_bytes();
+            assert_eq!(tiny_used_bytes, 16 * NUM_OF_OBJECTS);
+            let tiny_pages = pages_of(&allocated);
+            assert_eq!(
+                tiny_pages,
+                (16 * NUM_OF_OBJECTS).div_ceil(constants::PAGE_SIZE)
+            );
+
+            // The same objects rounded up to the 64 bytes class spread over four times the pages.
+            allocator.reset();
+            let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+            let count = allocator.allocate_batch(layout, NUM_OF_OBJECTS, &mut allocated);
+            assert_eq!(count, NUM_OF_OBJECTS);
+            assert_eq!(allocator.used_bytes(), 4 * tiny_used_bytes);
+            assert_eq!(pages_of(&allocated), 4 * tiny_pages);
+
+            alloc::alloc::dealloc(heap, heap_layout);
+        }
+    }
+
+    #[test]
+    fn non_power_of_two_size_classes() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        // 1500 bytes wasted 548 bytes in the 2048 bytes class.
+        let layout = Layout::from_size_align(1500, align_of::<usize>()).unwrap();
+        assert_eq!(SlabAllocator::waste_for(&layout), 36);
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            for size in [96, 144, 384, 768, 1500] {
+                let layout = Layout::from_size_align(size, align_of::<usize>()).unwrap();
+                let class = SlabAllocator::size_class_for(&layout).unwrap();
+                assert_eq!(class as usize, SlabSize::try_from(size).unwrap() as usize);
+
+                let first = allocator.allocate(layout);
+                let second = allocator.allocate(layout);
+                assert!(!first.is_null() && !second.is_null());
+                assert!(crate::align::is_aligned(first as usize, class.align()));
+                assert_eq!((second as usize).abs_diff(first as usize), class as usize);
+
+                allocator.deallocate(second, layout);
+                allocator.deallocate(first, layout);
+                assert_eq!(allocator.allocate(layout), first);
+                allocator.deallocate(first, layout);
+            }
+            assert_eq!(allocator.used_bytes(), 0);
+        }
+    }
+
+    #[test]
+    fn fallback_usage() {
+        // `linked_list_allocator` gets 16 pages.
+        let heap_size = constants::NUM_OF_SLABS * 16 * constants::PAGE_SIZE;
+        let heap_layout = Layout::from_size_align(heap_size, constants::PAGE_SIZE).unwrap();
+        let layout = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let heap = alloc::alloc::alloc(heap_layout);
+            let mut allocator = SlabAllocator::new(heap as usize, heap_size);
+            let free_bytes = allocator.fallback_free_bytes();
+            assert_eq!(allocator.fallback_used_bytes(), 0);
+
+            let first = allocator.allocate(layout);
+            let second = allocator.allocate(layout);
+            assert_eq!(allocator.fallback_used_bytes(), 2 * 5000);
+            assert_eq!(allocator.fallback_free_bytes(), free_bytes - 2 * 5000);
+
+            // slab objects don't use the fallback region.
+            let small = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+            let _ = allocator.allocate(small);
+            assert_eq!(allocator.fallback_used_bytes(), 2 * 5000);
+
+            allocator.deallocate(first, layout);
+            assert_eq!(allocator.fallback_used_bytes(), 5000);
+            allocator.deallocate(second, layout);
+            assert_eq!(allocator.fallback_used_bytes(), 0);
+            assert_eq!(allocator.fallback_free_bytes(), free_bytes);
+
+            alloc::alloc::dealloc(heap, heap_layout);
+        }
+    }
+
+    #[test]
+    fn deallocate_without_layout() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let small = Layout::from_size_align(100, 32).unwrap();
+        let large = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let ptr = allocator.allocate(small);
+            assert!(allocator.deallocate_unsized(ptr));
+            assert_eq!(allocator.used_bytes(), 0);
+            // the object was returned to the 128 bytes class.
+            assert_eq!(allocator.allocate(small), ptr);
+            allocator.deallocate(ptr, small);
+
+            let ptr = allocator.allocate(large);
+            assert!(!allocator.deallocate_unsized(ptr));
+            assert_eq!(allocator.used_bytes(), 5000);
+            allocator.deallocate(ptr, large);
+        }
+    }
+
+    #[test]
+    fn custom_fallback() {
+        use crate::Fallback;
+
+        /// Bump allocator that counts calls.
+        struct CountingFallback {
+            next: usize,
+            end: usize,
+            allocs: usize,
+            deallocs: usize,
+        }
+
+        impl Fallback for CountingFallback {
+            unsafe fn init(start_addr: usize, size: usize) -> Self {
+                CountingFallback {
+                    next: start_addr,
+                    end: start_addr + size,
+                    allocs: 0,
+                    deallocs: 0,
+                }
+            }
+
+            fn alloc_fallback(&mut self, layout: Layout) -> *mut u8 {
+                self.allocs += 1;
+                let start = crate::align::align_up(self.next, layout.align()).unwrap();
+                if start + layout.size() > self.end {
+                    return core::ptr::null_mut();
+                }
+                self.next = start + layout.size();
+                start as *mut u8
+            }
+
+            unsafe fn dealloc_fallback(&mut self, _ptr: *mut u8, _layout: Layout) {
+                self.deallocs += 1;
+            }
+
+            fn used_bytes(&self) -> usize {
+                0
+            }
+
+            fn free_bytes(&self) -> usize {
+                self.end - self.next
+            }
+        }
+
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let small = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+        let large = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let mut allocator = SlabAllocator::<CountingFallback>::with_fallback(
+                &dummy_heap.heap_space as *const u8 as usize,
+                HEAP_SIZE,
+            );
+            let ptr = allocator.allocate(small);
+            allocator.deallocate(ptr, small);
+            assert_eq!(allocator.fallback.allocs, 0);
+
+            let ptr = allocator.allocate(large);
+            assert!(allocator.fallback_region().contains(&(ptr as usize)));
+            allocator.deallocate(ptr, large);
+            assert_eq!(allocator.fallback.allocs, 1);
+            assert_eq!(allocator.fallback.deallocs, 1);
+        }
+    }
+
+    #[test]
+    fn iterate_slabs() {
+        use crate::SlabKind;
+
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let page = Layout::from_size_align(4096, align_of::<usize>()).unwrap();
+        let small = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+        let capacity = SlabAllocator::slab_allocated_size(HEAP_SIZE) / 4096;
+        let mut allocated = [core::ptr::null_mut(); 8];
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            assert_eq!(
+                allocator.allocate_batch(page, capacity, &mut allocated),
+                capacity
+            );
+            let _ = allocator.allocate(small);
+
+            let count = |kind| {
+                allocator
+                    .iter_all_slabs()
+                    .filter(|info| info.kind == kind)
+                    .count()
+            };
+            assert_eq!(count(SlabKind::Full), 1);
+            assert_eq!(count(SlabKind::Partial), 1);
+            assert_eq!(count(SlabKind::Empty), SlabSize::ALL.len() - 2);
+
+            let info = allocator
+                .iter_all_slabs()
+                .find(|info| info.object_size == SlabSize::Slab64Bytes)
+                .unwrap();
+            assert_eq!(info.used_objects, 1);
+            assert_eq!(info.num_of_objects, info.size / 64);
+            // iterating doesn't modify the free lists.
+            assert_eq!(allocator.iter_all_slabs().count(), SlabSize::ALL.len());
+            assert_eq!(allocator.used_bytes(), capacity * 4096 + 64);
+        }
+    }
+
+    #[test]
+    fn allocation_histogram() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let sizes = [8, 8, 8, 100, 100, 5000];
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            for size in sizes {
+                let layout = Layout::from_size_align(size, align_of::<usize>()).unwrap();
+                let ptr = allocator.allocate(layout);
+                allocator.deallocate(ptr, layout);
+            }
+            let layout = Layout::from_size_align(100, align_of::<usize>()).unwrap();
+            let _ = allocator.allocate(layout);
+
+            let mut expected = [0; SlabSize::ALL.len()];
+            expected[0] = 3; // 8 bytes
+            expected[5] = 3; // 128 bytes
+            assert_eq!(allocator.allocation_histogram(), expected);
+
+            allocator.reset_histogram();
+            assert_eq!(allocator.allocation_histogram(), [0; SlabSize::ALL.len()]);
+        }
+    }
+
+    #[test]
+    fn allocate_zeroed() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            for size in [256, 5000] {
+                let layout = Layout::from_size_align(size, align_of::<usize>()).unwrap();
+                let ptr = allocator.allocate_zeroed(layout);
+                ptr.write_bytes(0xff, size);
+                allocator.deallocate(ptr, layout);
+
+                let ptr = allocator.allocate_zeroed(layout);
+                let contents = core::slice::from_raw_parts(ptr, size);
+                assert!(contents.iter().all(|b| *b == 0));
+                allocator.deallocate(ptr, layout);
+            }
+        }
+    }
+
+    #[test]
+    fn class_of_matches_allocation() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        assert_eq!(WildScreenAlloc::size_classes().len(), SlabSize::ALL.len());
+        assert!(WildScreenAlloc::size_classes()
+            .windows(2)
+            .all(|pair| pair[0] < pair[1]));
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            for size in (0..=4200).step_by(7) {
+                for align in [1, 8, 64, 512] {
+                    let layout = Layout::from_size_align(size, align).unwrap();
+                    let ptr = allocator.allocate(layout);
+                    let landed = allocator
+                        .cache_index_of(ptr)
+                        .map(|index| WildScreenAlloc::size_classes()[index]);
+                    assert_eq!(WildScreenAlloc::class_of(layout), landed);
+                    allocator.deallocate(ptr, layout);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn prealloc() {
+        use crate::AllocError;
+
+        // each size class gets 8 pages.
+        let heap_size = constants::NUM_OF_SLABS * 8 * constants::PAGE_SIZE;
+        let heap_layout = Layout::from_size_align(heap_size, constants::PAGE_SIZE).unwrap();
+        let layout = Layout::from_size_align(256, align_of::<usize>()).unwrap();
+        let capacity = 8 * constants::PAGE_SIZE / 256;
+
+        unsafe {
+            let heap = alloc::alloc::alloc(heap_layout);
+            let mut allocator = SlabAllocator::new(heap as usize, heap_size);
+            assert_eq!(allocator.prealloc(layout, 100), Ok(()));
+            for _ in 0..100 {
+                let ptr = allocator.allocate(layout);
+                assert_eq!(allocator.cache_index_of(ptr), Some(7));
+            }
+
+            assert_eq!(
+                allocator.prealloc(layout, capacity),
+                Err(AllocError::Insufficient {
+                    available: capacity - 100
+                })
+            );
+            let large = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+            assert_eq!(
+                allocator.prealloc(large, 1),
+                Err(AllocError::Insufficient { available: 0 })
+            );
+
+            alloc::alloc::dealloc(heap, heap_layout);
+        }
+    }
+
+    #[test]
+    fn walk_live() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layouts = [8, 100, 1000].map(|size| Layout::from_size_align(size, 8).unwrap());
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let objects = layouts.map(|layout| allocator.allocate(layout));
+            let freed = allocator.allocate(layouts[1]);
+            allocator.deallocate(freed, layouts[1]);
+
+            let mut visited = [(core::ptr::null(), 0); 3];
+            let mut count = 0;
+            allocator.walk_live(|object, size| {
+                visited[count] = (object, size);
+                count += 1;
+            });
+            assert_eq!(count, 3);
+            assert_eq!(
+                visited,
+                [
+                    (objects[0] as *const u8, 8),
+                    (objects[1] as *const u8, 128),
+                    (objects[2] as *const u8, 1024)
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn peak_covers_odd_sized_fallback() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(4097, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let ptr = allocator.allocate(layout);
+            assert!(!ptr.is_null());
+            // linked_list_allocator rounds 4097 bytes up to 4104 bytes.
+            assert_eq!(allocator.used_bytes(), allocator.fallback_used_bytes());
+            assert_eq!(allocator.snapshot().live_bytes, allocator.used_bytes());
+            assert!(allocator.peak_bytes() >= allocator.used_bytes());
+
+            allocator.deallocate(ptr, layout);
+            assert_eq!(allocator.used_bytes(), 0);
+            assert_eq!(allocator.fallback_used_bytes(), 0);
+        }
+    }
 
-mod slab;
+    #[test]
+    fn claim_and_release() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+        let class_size = SlabAllocator::slab_allocated_size(HEAP_SIZE);
+        let start_addr = &dummy_heap.heap_space as *const u8 as usize;
+        // the middle of the region of the 64 bytes class.
+        let claimed = start_addr + 3 * class_size + 1000..start_addr + 3 * class_size + 2000;
 
-use alloc::alloc::{GlobalAlloc, Layout};
-use slab::{SlabCache, SlabSize};
-use spin::Mutex;
+        unsafe {
+            let mut allocator = SlabAllocator::new(start_addr, HEAP_SIZE);
+            let free_bytes = allocator.caches[3].free_count() * 64;
+            assert_eq!(allocator.claim(claimed.start, claimed.len()), Ok(()));
+            // 960..2048
+            assert_eq!(allocator.used_bytes(), 17 * 64);
+            assert_eq!(allocator.snapshot().live_bytes, 17 * 64);
+            assert_eq!(allocator.snapshot().live_objects[3], 17);
+            assert_eq!(allocator.peak_bytes(), 17 * 64);
+            allocator.freeze();
+            let mut allocated = 0;
+            loop {
+                let ptr = allocator.allocate(layout);
+                if ptr.is_null() {
+                    break;
+                }
+                assert!(!(claimed.start - 63..claimed.end).contains(&(ptr as usize)));
+                allocated += 64;
+            }
+            assert_eq!(allocated, free_bytes - 17 * 64);
 
-/// Constants.
-mod constants {
-    /// Number of slab allocator size.
-    pub const NUM_OF_SLABS: usize = 8;
-    /// Page size.
-    pub const PAGE_SIZE: usize = 4096;
-}
+            assert_eq!(allocator.release(claimed.start, claimed.len()), Ok(()));
+            assert_eq!(allocator.caches[3].free_count(), 17);
+            assert_eq!(allocator.used_bytes(), allocated);
+        }
+    }
 
-/// Slab allocator that provide global allocator.
-/// If allocate size over 4096 bytes, it delegate to `linked_list_allocator`.
-pub struct SlabAllocator {
-    slab_64_bytes: SlabCache,
-    slab_128_bytes: SlabCache,
-    slab_256_bytes: SlabCache,
-    slab_512_bytes: SlabCache,
-    slab_1024_bytes: SlabCache,
-    slab_2048_bytes: SlabCache,
-    slab_4096_bytes: SlabCache,
-    linked_list_allocator: linked_list_allocator::Heap,
-}
+    #[test]
+    fn release_twice() {
+        use crate::ClaimError;
 
-impl SlabAllocator {
-    /// Return new `SlabAllocator`.
-    /// # Safety
-    /// `start_addr` must be aligned 4096.
-    ///
-    /// # Panics
-    /// If `start_addr` isn't aligned 4096, this function will panic.
-    #[must_use]
-    pub unsafe fn new(start_addr: usize, heap_size: usize) -> Self {
-        assert!(
-            start_addr % constants::PAGE_SIZE == 0,
-            "Start address should be page aligned"
-        );
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let start_addr = &dummy_heap.heap_space as *const u8 as usize;
+        let claimed = start_addr + 100..start_addr + 200;
 
-        let slab_allocated_size = heap_size / constants::NUM_OF_SLABS;
-        SlabAllocator {
-            slab_64_bytes: SlabCache::new(start_addr, slab_allocated_size, SlabSize::Slab64Bytes),
-            slab_128_bytes: SlabCache::new(
-                start_addr + slab_allocated_size,
-                slab_allocated_size,
-                SlabSize::Slab128Bytes,
-            ),
-            slab_256_bytes: SlabCache::new(
-                start_addr + 2 * slab_allocated_size,
-                slab_allocated_size,
-                SlabSize::Slab256Bytes,
-            ),
-            slab_512_bytes: SlabCache::new(
-                start_addr + 3 * slab_allocated_size,
-                slab_allocated_size,
-                SlabSize::Slab512Bytes,
-            ),
-            slab_1024_bytes: SlabCache::new(
-                start_addr + 4 * slab_allocated_size,
-                slab_allocated_size,
-                SlabSize::Slab1024Bytes,
-            ),
-            slab_2048_bytes: SlabCache::new(
-                start_addr + 5 * slab_allocated_size,
-                slab_allocated_size,
-                SlabSize::Slab2048Bytes,
-            ),
-            slab_4096_bytes: SlabCache::new(
-                start_addr + 6 * slab_allocated_size,
-                slab_allocated_size,
-                SlabSize::Slab4096Bytes,
-            ),
-            linked_list_allocator: linked_list_allocator::Heap::new(
-                (start_addr + 7 * slab_allocated_size) as *mut u8,
-                slab_allocated_size,
-            ),
+        unsafe {
+            let mut allocator = SlabAllocator::new(start_addr, HEAP_SIZE);
+            let free_count = allocator.caches[0].free_count();
+            assert_eq!(allocator.claim(claimed.start, claimed.len()), Ok(()));
+            assert_eq!(allocator.release(claimed.start, claimed.len()), Ok(()));
+            assert_eq!(
+                allocator.release(claimed.start, claimed.len()),
+                Err(ClaimError::NotClaimed)
+            );
+
+            // the free list has no duplicated objects.
+            assert_eq!(allocator.caches[0].free_count(), free_count);
+            assert_eq!(allocator.used_bytes(), 0);
         }
     }
 
-    /// Allocates a new object.
-    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
-        match Self::get_slab_size(&layout) {
-            Some(slab::SlabSize::Slab64Bytes) => self.slab_64_bytes.allocate(),
-            Some(slab::SlabSize::Slab128Bytes) => self.slab_128_bytes.allocate(),
-            Some(slab::SlabSize::Slab256Bytes) => self.slab_256_bytes.allocate(),
-            Some(slab::SlabSize::Slab512Bytes) => self.slab_512_bytes.allocate(),
-            Some(slab::SlabSize::Slab1024Bytes) => self.slab_1024_bytes.allocate(),
-            Some(slab::SlabSize::Slab2048Bytes) => self.slab_2048_bytes.allocate(),
-            Some(slab::SlabSize::Slab4096Bytes) => self.slab_4096_bytes.allocate(),
-            None => match self.linked_list_allocator.allocate_first_fit(layout) {
-                Ok(ptr) => ptr.as_ptr(),
-                Err(()) => core::ptr::null_mut(),
-            },
+    #[test]
+    fn claim_errors() {
+        use crate::ClaimError;
+
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(8, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let fallback_region = allocator.fallback_region();
+            assert_eq!(
+                allocator.claim(fallback_region.start, 8),
+                Err(ClaimError::OutOfRange)
+            );
+            assert_eq!(
+                allocator.claim(fallback_region.start - 8, 16),
+                Err(ClaimError::OutOfRange)
+            );
+            // the end of the range overflows.
+            assert_eq!(
+                allocator.claim(fallback_region.start - 8, usize::MAX),
+                Err(ClaimError::OutOfRange)
+            );
+            assert_eq!(
+                allocator.release(fallback_region.start - 8, usize::MAX),
+                Err(ClaimError::NotClaimed)
+            );
+            assert_eq!(
+                allocator.claim(fallback_region.start - 8, 0),
+                Err(ClaimError::Empty)
+            );
+
+            let ptr = allocator.allocate(layout) as usize;
+            assert_eq!(allocator.claim(ptr, 8), Err(ClaimError::InUse));
+            assert_eq!(allocator.claim(ptr + 8, 8), Ok(()));
+            // overlaps the claimed object.
+            assert_eq!(allocator.claim(ptr + 12, 8), Err(ClaimError::InUse));
+
+            for index in 2..=constants::MAX_CLAIMS {
+                assert_eq!(allocator.claim(ptr + index * 8, 8), Ok(()));
+            }
+            assert_eq!(
+                allocator.claim(ptr + 100 * 8, 8),
+                Err(ClaimError::TooManyClaims)
+            );
+            assert_eq!(allocator.used_bytes(), (1 + constants::MAX_CLAIMS) * 8);
         }
     }
 
-    /// Deallocate(free) object.
-    /// # Safety
-    /// Given pointer must be valid.
-    ///
-    /// # Panics
-    /// If given ptr is null, it will panic.
-    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
-        match Self::get_slab_size(&layout) {
-            Some(slab::SlabSize::Slab64Bytes) => self.slab_64_bytes.deallocate(ptr),
-            Some(slab::SlabSize::Slab128Bytes) => self.slab_128_bytes.deallocate(ptr),
-            Some(slab::SlabSize::Slab256Bytes) => self.slab_256_bytes.deallocate(ptr),
-            Some(slab::SlabSize::Slab512Bytes) => self.slab_512_bytes.deallocate(ptr),
-            Some(slab::SlabSize::Slab1024Bytes) => self.slab_1024_bytes.deallocate(ptr),
-            Some(slab::SlabSize::Slab2048Bytes) => self.slab_2048_bytes.deallocate(ptr),
-            Some(slab::SlabSize::Slab4096Bytes) => self.slab_4096_bytes.deallocate(ptr),
-            None => self
-                .linked_list_allocator
-                .deallocate(core::ptr::NonNull::new(ptr).unwrap(), layout),
+    #[test]
+    fn freeze() {
+        use crate::AllocError;
+
+        let heap_size = constants::NUM_OF_SLABS * 8 * constants::PAGE_SIZE;
+        let heap_layout = Layout::from_size_align(heap_size, constants::PAGE_SIZE).unwrap();
+        let small = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+        let large = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let heap = alloc::alloc::alloc(heap_layout);
+            let mut allocator = SlabAllocator::new(heap as usize, heap_size);
+            let large_ptr = allocator.allocate(large);
+            assert!(!large_ptr.is_null());
+
+            allocator.freeze();
+            assert!(allocator.is_frozen());
+            let ptr = allocator.allocate(small);
+            assert!(!ptr.is_null());
+            assert!(allocator.allocate(large).is_null());
+            assert_eq!(allocator.try_allocate(large), Err(AllocError::Frozen));
+            assert!(allocator.allocate_pages(1, constants::PAGE_SIZE).is_none());
+            allocator.deallocate(large_ptr, large);
+
+            allocator.thaw();
+            let large_ptr = allocator.allocate(large);
+            assert!(!large_ptr.is_null());
+            let huge = Layout::from_size_align(heap_size, align_of::<usize>()).unwrap();
+            assert_eq!(allocator.try_allocate(huge), Err(AllocError::OutOfMemory));
+
+            allocator.deallocate(large_ptr, large);
+            allocator.deallocate(ptr, small);
+            alloc::alloc::dealloc(heap, heap_layout);
         }
     }
 
-    /// Convert `layout.size` to `SlabSize`
-    fn get_slab_size(layout: &Layout) -> Option<SlabSize> {
-        let slab_size = match layout.size() {
-            0..=64 => Some(SlabSize::Slab64Bytes),
-            65..=128 => Some(SlabSize::Slab128Bytes),
-            129..=256 => Some(SlabSize::Slab256Bytes),
-            257..=512 => Some(SlabSize::Slab512Bytes),
-            513..=1024 => Some(SlabSize::Slab1024Bytes),
-            1025..=2048 => Some(SlabSize::Slab2048Bytes),
-            2049..=4096 => Some(SlabSize::Slab4096Bytes),
-            _ => None,
-        };
-
-        slab_size.map(|size| {
-            if layout.align() <= size as usize {
-                size
-            } else {
-                // unaligned layout
-                SlabSize::Slab4096Bytes
-            }
-        })
-    }
-}
+    #[test]
+    fn reallocate_in_same_class() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(40, align_of::<usize>()).unwrap();
 
-pub struct WildScreenAlloc(Mutex<Option<SlabAllocator>>);
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let ptr = allocator.allocate(layout);
+            let used_bytes = allocator.used_bytes();
 
-impl WildScreenAlloc {
-    /// Return empty `WildScreenAlloc`.
-    /// This method exist for to initialize after heap address available.
-    /// ```no_run
-    /// use wild_screen_alloc::WildScreenAlloc;
-    ///
-    /// #[global_allocator]
-    /// static ALLOCATOR: WildScreenAlloc = WildScreenAlloc::empty();
-    ///
-    /// pub fn init_heap() { /* initialize ALLOCATOR */ }
-    /// ```
-    pub const fn empty() -> Self {
-        WildScreenAlloc(Mutex::new(None))
+            // 40, 50 and 60 bytes are all in the 64 bytes class.
+            let grown = allocator.reallocate(ptr, layout, 50);
+            assert_eq!(grown, ptr);
+            let layout = Layout::from_size_align(50, layout.align()).unwrap();
+            let grown = allocator.reallocate(grown, layout, 60);
+            assert_eq!(grown, ptr);
+            assert_eq!(allocator.used_bytes(), used_bytes);
+
+            // it moves to the 128 bytes class.
+            let layout = Layout::from_size_align(60, layout.align()).unwrap();
+            let moved = allocator.reallocate(grown, layout, 100);
+            assert_ne!(moved, ptr);
+            assert_eq!(allocator.used_bytes(), 128);
+        }
     }
 
-    /// Initialize allocator.
-    /// ```no_run
-    /// use wild_screen_alloc::WildScreenAlloc;
-    ///
-    /// #[global_allocator]
-    /// static mut ALLOCATOR: WildScreenAlloc = WildScreenAlloc::empty();
-    ///
-    /// pub fn init_heap() {
-    ///     let heap_start = 0x8020_0000;
-    ///     let heap_size = 0x8000;
-    ///     unsafe {
-    ///         ALLOCATOR.init(heap_start, heap_size);
-    ///     }
-    /// }
-    /// ```
-    ///
-    /// # Safety
-    /// `start_addr` must be aligned 4096.
-    pub unsafe fn init(&mut self, start_addr: usize, heap_size: usize) {
-        *self.0.lock() = Some(SlabAllocator::new(start_addr, heap_size));
+    #[test]
+    fn reallocate_zeroed() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layout = Layout::from_size_align(40, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let ptr = allocator.allocate(layout);
+            ptr.write_bytes(0xaa, 64);
+
+            // it grows in place.
+            let grown = allocator.reallocate_zeroed(ptr, layout, 60);
+            assert_eq!(grown, ptr);
+            let grown = core::slice::from_raw_parts(grown, 64);
+            assert!(grown[..40].iter().all(|&byte| byte == 0xaa));
+            assert!(grown[40..60].iter().all(|&byte| byte == 0));
+            assert!(grown[60..].iter().all(|&byte| byte == 0xaa));
+
+            // it moves to the 128 bytes class.
+            let layout = Layout::from_size_align(60, layout.align()).unwrap();
+            let moved = allocator.reallocate_zeroed(ptr, layout, 100);
+            assert_ne!(moved, ptr);
+            let moved = core::slice::from_raw_parts(moved, 100);
+            assert!(moved[..40].iter().all(|&byte| byte == 0xaa));
+            assert!(moved[40..].iter().all(|&byte| byte == 0));
+        }
     }
 
-    /// Create new allocator locked by mutex.
-    /// # Safety
-    /// `start_addr` must be aligned 4096.
-    pub unsafe fn new(start_addr: usize, heap_size: usize) -> Self {
-        WildScreenAlloc(Mutex::new(Some(SlabAllocator::new(start_addr, heap_size))))
+    #[test]
+    fn reallocate_across_fallback() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let small = Layout::from_size_align(100, align_of::<usize>()).unwrap();
+        let large = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let mut allocator =
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+
+            // slab class -> linked_list_allocator
+            let ptr = allocator.allocate(small);
+            ptr.write_bytes(0xaa, small.size());
+            let grown = allocator.reallocate(ptr, small, large.size());
+            assert!(!grown.is_null());
+            let contents = core::slice::from_raw_parts(grown, small.size());
+            assert!(contents.iter().all(|b| *b == 0xaa));
+            // the old object was returned to its class.
+            assert_eq!(allocator.allocate(small), ptr);
+            allocator.deallocate(ptr, small);
+
+            // linked_list_allocator -> slab class
+            grown.write_bytes(0xbb, large.size());
+            let shrunk = allocator.reallocate(grown, large, small.size());
+            assert!(!shrunk.is_null());
+            let contents = core::slice::from_raw_parts(shrunk, small.size());
+            assert!(contents.iter().all(|b| *b == 0xbb));
+
+            allocator.deallocate(shrunk, small);
+            assert_eq!(allocator.used_bytes(), 0);
+        }
     }
-}
 
-unsafe impl GlobalAlloc for WildScreenAlloc {
-    /// Just call `SlabAllocator::allocte`.
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        match *self.0.lock() {
-            Some(ref mut allocator) => allocator.allocate(layout),
-            None => panic!("The allocator is not initialized"),
+    #[test]
+    fn size_class_boundaries() {
+        /// Linear search over all size classes.
+        fn reference_class(size: usize) -> Option<SlabSize> {
+            SlabSize::ALL
+                .into_iter()
+                .find(|class| size <= *class as usize)
+        }
+
+        let extra_sizes = [0x1F_FFFF, 0x20_0000, isize::MAX as usize];
+        for size in (0..=4097).chain(extra_sizes) {
+            let layout = Layout::from_size_align(size, 1).unwrap();
+            assert_eq!(
+                SlabAllocator::size_class_for(&layout),
+                reference_class(size),
+                "size: {size}"
+            );
         }
     }
 
-    /// Just call `SlabAllocator::deallocate`.
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        match *self.0.lock() {
-            Some(ref mut allocator) => allocator.deallocate(ptr, layout),
-            None => panic!("The allocator is not initialized"),
+    #[test]
+    fn snapshot() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let small = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+        let large = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let allocator =
+                WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            assert_eq!(allocator.snapshot_locked(), Some(HeapSnapshot::default()));
+
+            let objects = [0; 4].map(|_| allocator.alloc(small));
+            let large_ptr = allocator.alloc(large);
+            allocator.dealloc(objects[0], small);
+            allocator.dealloc(objects[1], small);
+
+            let snapshot = allocator.snapshot_locked().unwrap();
+            assert_eq!(snapshot.allocations, 5);
+            assert_eq!(snapshot.frees, 2);
+            assert_eq!(snapshot.live_objects[3], 2);
+            assert_eq!(
+                snapshot.allocations - snapshot.frees,
+                snapshot.live_objects.iter().sum::<usize>() + 1
+            );
+            assert_eq!(snapshot.live_bytes, 2 * 64 + 5000);
+            assert_eq!(snapshot.peak_bytes, 4 * 64 + 5000);
+
+            allocator.dealloc(objects[2], small);
+            allocator.dealloc(objects[3], small);
+            allocator.dealloc(large_ptr, large);
         }
+        assert_eq!(WildScreenAlloc::empty().snapshot_locked(), None);
     }
-}
 
-#[cfg(test)]
-mod alloc_tests {
-    use crate::{constants, SlabAllocator};
-    use alloc::alloc::Layout;
-    use core::mem::{align_of, size_of};
+    #[test]
+    #[cfg(feature = "alloc-tags")]
+    fn allocation_tags() {
+        use crate::TagStats;
 
-    const HEAP_SIZE: usize = 16 * constants::PAGE_SIZE;
-    #[repr(align(4096))]
-    struct DummyHeap {
-        heap_space: [u8; HEAP_SIZE],
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let small = Layout::from_size_align(40, align_of::<usize>()).unwrap();
+        let large = Layout::from_size_align(5000, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let allocator =
+                WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let (first, second) =
+                allocator.with_tag(1, || (allocator.alloc(small), allocator.alloc(large)));
+            let third = allocator.with_tag(2, || allocator.alloc(small));
+            // the tag is restored to the default.
+            let fourth = allocator.alloc(small);
+
+            let tags = allocator.stats().unwrap().tags;
+            assert_eq!(
+                tags[0],
+                TagStats {
+                    bytes: 40,
+                    count: 1
+                }
+            );
+            assert_eq!(
+                tags[1],
+                TagStats {
+                    bytes: 5040,
+                    count: 2
+                }
+            );
+            assert_eq!(
+                tags[2],
+                TagStats {
+                    bytes: 40,
+                    count: 1
+                }
+            );
+
+            // deallocation is attributed to the tag at allocation.
+            allocator.with_tag(2, || allocator.dealloc(second, large));
+            allocator.dealloc(third, small);
+            let stats = allocator.stats().unwrap();
+            assert_eq!(
+                stats.tags[1],
+                TagStats {
+                    bytes: 40,
+                    count: 1
+                }
+            );
+            assert_eq!(stats.tags[2], TagStats::default());
+            assert_eq!(stats.untagged, 0);
+
+            allocator.dealloc(first, small);
+            allocator.dealloc(fourth, small);
+        }
     }
 
     #[test]
-    fn create_allocator() {
+    #[cfg(feature = "alloc-tags")]
+    fn tag_restored_after_panic() {
+        use crate::TagStats;
+
         let dummy_heap = DummyHeap {
             heap_space: [0_u8; HEAP_SIZE],
         };
+        let layout = Layout::from_size_align(40, align_of::<usize>()).unwrap();
 
         unsafe {
-            let _ = SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let allocator =
+                WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                allocator.with_tag(3, || panic!("panic in with_tag"));
+            }));
+            assert!(result.is_err());
+
+            let ptr = allocator.alloc(layout);
+            let tags = allocator.stats().unwrap().tags;
+            assert_eq!(
+                tags[0],
+                TagStats {
+                    bytes: 40,
+                    count: 1
+                }
+            );
+            assert_eq!(tags[3], TagStats::default());
+            allocator.dealloc(ptr, layout);
         }
     }
 
     #[test]
-    fn alloc_and_free_test() {
+    #[cfg(feature = "alloc-tags")]
+    fn tags_after_resize_in_place() {
+        use crate::TagStats;
+
         let dummy_heap = DummyHeap {
             heap_space: [0_u8; HEAP_SIZE],
         };
-        let size = size_of::<usize>() * 2;
-        let layout = Layout::from_size_align(size, align_of::<usize>());
+        let layout = Layout::from_size_align(100, align_of::<usize>()).unwrap();
 
         unsafe {
             let mut allocator =
                 SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
-            let addr = allocator.allocate(layout.clone().unwrap());
-            assert!(!addr.is_null());
+            allocator.set_tag(3);
+            let ptr = allocator.allocate(layout);
+            // 100 and 120 bytes are in the 128 bytes class.
+            assert_eq!(allocator.reallocate(ptr, layout, 120), ptr);
+            assert_eq!(
+                allocator.stats().tags[3],
+                TagStats {
+                    bytes: 120,
+                    count: 1
+                }
+            );
 
-            allocator.deallocate(addr, layout.unwrap());
+            let grown = Layout::from_size_align(120, align_of::<usize>()).unwrap();
+            assert!(allocator.shrink_in_place(ptr, grown, 97));
+            assert_eq!(
+                allocator.stats().tags[3],
+                TagStats {
+                    bytes: 97,
+                    count: 1
+                }
+            );
+
+            allocator.deallocate(
+                ptr,
+                Layout::from_size_align(97, align_of::<usize>()).unwrap(),
+            );
+            assert_eq!(allocator.stats().tags[3], TagStats::default());
         }
     }
 
     #[test]
-    fn alloc_4096_bytes() {
+    fn cache_index_at_region_ends() {
         let dummy_heap = DummyHeap {
             heap_space: [0_u8; HEAP_SIZE],
         };
-        let size = 4096;
-        let layout = Layout::from_size_align(size, align_of::<usize>());
 
         unsafe {
-            let mut allocator =
+            let allocator =
                 SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
-            let addr = allocator.allocate(layout.clone().unwrap());
-            assert!(!addr.is_null());
+            for (index, slab) in allocator.iter_all_slabs().enumerate() {
+                assert!(crate::align::is_aligned(
+                    slab.start_addr,
+                    constants::PAGE_SIZE
+                ));
+                let first = slab.start_addr as *mut u8;
+                let last = (slab.start_addr + slab.size - 1) as *mut u8;
+                assert_eq!(allocator.cache_index_of(first), Some(index));
+                assert_eq!(allocator.cache_index_of(last), Some(index));
+            }
+        }
+    }
 
-            allocator.deallocate(addr, layout.unwrap());
+    #[test]
+    fn size_used_free() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layouts = [8, 100, 5000].map(|size| Layout::from_size_align(size, 8).unwrap());
+
+        let empty = WildScreenAlloc::empty();
+        assert_eq!((empty.size(), empty.used(), empty.free()), (0, 0, 0));
+
+        unsafe {
+            let allocator =
+                WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            assert_eq!(allocator.size(), HEAP_SIZE);
+            assert_eq!(allocator.free(), HEAP_SIZE);
+
+            let objects = layouts.map(|layout| allocator.alloc(layout));
+            assert_eq!(allocator.used(), 8 + 128 + 5000);
+            assert_eq!(allocator.used() + allocator.free(), allocator.size());
+
+            for (ptr, layout) in objects.into_iter().zip(layouts) {
+                allocator.dealloc(ptr, layout);
+                assert_eq!(allocator.used() + allocator.free(), allocator.size());
+            }
+            assert_eq!(allocator.used(), 0);
         }
     }
 
     #[test]
-    fn alloc_4104_bytes() {
+    fn lock_free_snapshot() {
         let dummy_heap = DummyHeap {
             heap_space: [0_u8; HEAP_SIZE],
         };
-        let size = 4104;
-        let layout = Layout::from_size_align(size, align_of::<usize>());
+        let layouts = [8, 64, 100, 5000].map(|size| Layout::from_size_align(size, 8).unwrap());
 
         unsafe {
-            let mut allocator =
-                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
-            let addr = allocator.allocate(layout.clone().unwrap());
-            assert!(!addr.is_null());
+            let allocator =
+                WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            assert_eq!(allocator.snapshot(), HeapSnapshot::default());
 
-            allocator.deallocate(addr, layout.unwrap());
+            let mut last = allocator.snapshot();
+            for round in 0..10 {
+                let objects = layouts.map(|layout| allocator.alloc(layout));
+                let ptr = allocator.realloc(objects[0], layouts[0], 200);
+                let snapshot = allocator.snapshot();
+                assert!(snapshot.allocations >= last.allocations);
+                assert!(snapshot.frees >= last.frees);
+                assert!(snapshot.allocations >= snapshot.frees);
+                assert_eq!(snapshot.allocations - snapshot.frees, 4);
+                last = snapshot;
+
+                if round % 2 == 0 {
+                    allocator.reset_peak();
+                }
+                allocator.dealloc(ptr, Layout::from_size_align(200, 8).unwrap());
+                for (&object, layout) in objects[1..].iter().zip(&layouts[1..]) {
+                    allocator.dealloc(object, *layout);
+                }
+            }
+
+            // it matches the locked snapshot at quiescence.
+            let snapshot = allocator.snapshot();
+            assert_eq!(Some(snapshot), allocator.snapshot_locked());
+            assert_eq!(snapshot.allocations, snapshot.frees);
+            assert_eq!(snapshot.live_bytes, 0);
+
+            allocator.reset();
+            assert_eq!(allocator.snapshot(), HeapSnapshot::default());
         }
     }
 
     #[test]
-    fn alloc_8096_bytes() {
+    #[cfg(any(feature = "critical-section", not(feature = "single_threaded")))]
+    fn snapshot_while_locked() {
         let dummy_heap = DummyHeap {
             heap_space: [0_u8; HEAP_SIZE],
         };
-        let size = 8096;
-        let layout = Layout::from_size_align(size, align_of::<usize>());
+        let layout = Layout::from_size_align(64, align_of::<usize>()).unwrap();
+
+        unsafe {
+            let allocator =
+                WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
+            let ptr = allocator.alloc(layout);
+
+            let locked = allocator.allocator.lock();
+            let stats = std::thread::scope(|s| s.spawn(|| allocator.snapshot()).join().unwrap());
+            assert_eq!(Some(stats), locked.as_ref().map(SlabAllocator::snapshot));
+            drop(locked);
+
+            allocator.dealloc(ptr, layout);
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "critical-section", not(feature = "single_threaded")))]
+    fn snapshot_under_contention() {
+        const NUM_OF_THREADS: usize = 4;
+        const ROUNDS: usize = 1000;
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        let layouts = [8, 64, 100, 5000].map(|size| Layout::from_size_align(size, 8).unwrap());
+        let allocator = unsafe {
+            WildScreenAlloc::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE)
+        };
+        let done = AtomicBool::new(false);
+
+        std::thread::scope(|s| {
+            let reader = s.spawn(|| {
+                let mut last = allocator.snapshot();
+                while !done.load(Ordering::Relaxed) {
+                    let stats = allocator.snapshot();
+                    assert!(stats.allocations >= stats.frees);
+                    assert!(stats.allocations >= last.allocations);
+                    assert!(stats.frees >= last.frees);
+                    last = stats;
+                }
+            });
+            let writers = [0; NUM_OF_THREADS].map(|_| {
+                s.spawn(|| {
+                    for round in 0..ROUNDS {
+                        let layout = layouts[round % layouts.len()];
+                        unsafe {
+                            let ptr = allocator.alloc(layout);
+                            assert!(!ptr.is_null());
+                            allocator.dealloc(ptr, layout);
+                        }
+                    }
+                })
+            });
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            done.store(true, Ordering::Relaxed);
+            reader.join().unwrap();
+        });
+
+        // live = allocations - frees at quiescence.
+        let stats = allocator.snapshot();
+        assert_eq!(stats.allocations, NUM_OF_THREADS * ROUNDS);
+        assert_eq!(stats.allocations, stats.frees);
+        assert_eq!(stats.live_bytes, 0);
+        assert_eq!(Some(stats), allocator.snapshot_locked());
+    }
+
+    #[test]
+    fn allocation_size_bounds() {
+        let dummy_heap = DummyHeap {
+            heap_space: [0_u8; HEAP_SIZE],
+        };
+        assert_eq!(SlabAllocator::min_allocation_size(), 8);
+        assert_eq!(SlabAllocator::max_slab_size(), 4096);
 
         unsafe {
+            // the region of the fallback isn't a multiple of the word size.
             let mut allocator =
-                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE);
-            let addr = allocator.allocate(layout.clone().unwrap());
-            assert!(!addr.is_null());
+                SlabAllocator::new(&dummy_heap.heap_space as *const u8 as usize, HEAP_SIZE - 3);
+            let max_size = allocator.max_allocation_size();
+            assert!(max_size < allocator.fallback_region().len());
+            let layout = Layout::from_size_align(max_size + 1, align_of::<usize>()).unwrap();
+            assert!(allocator.allocate(layout).is_null());
 
-            allocator.deallocate(addr, layout.unwrap());
+            let layout = Layout::from_size_align(max_size, align_of::<usize>()).unwrap();
+            let ptr = allocator.allocate(layout);
+            assert!(!ptr.is_null());
+            allocator.deallocate(ptr, layout);
+
+            let layout = Layout::from_size_align(SlabAllocator::max_slab_size(), 1).unwrap();
+            assert_eq!(
+                SlabAllocator::size_class_for(&layout),
+                Some(SlabSize::Slab4096Bytes)
+            );
+            let layout = Layout::from_size_align(SlabAllocator::max_slab_size() + 1, 1).unwrap();
+            assert_eq!(SlabAllocator::size_class_for(&layout), None);
+        }
+    }
+
+    #[test]
+    fn allocate_over_page_alignment() {
+        let heap_size = constants::NUM_OF_SLABS * 8 * constants::PAGE_SIZE;
+        let heap_layout = Layout::from_size_align(heap_size, constants::PAGE_SIZE).unwrap();
+
+        unsafe {
+            let heap = alloc::alloc::alloc(heap_layout);
+            let mut allocator = SlabAllocator::new(heap as usize, heap_size);
+            for align in [8192, 16384] {
+                for size in [100, 4096] {
+                    let layout = Layout::from_size_align(size, align).unwrap();
+                    let ptr = allocator.allocate(layout);
+                    assert!(!ptr.is_null());
+                    assert!(crate::align::is_aligned(ptr as usize, align));
+                    allocator.deallocate(ptr, layout);
+                }
+            }
+            assert_eq!(allocator.used_bytes(), 0);
+
+            alloc::alloc::dealloc(heap, heap_layout);
+        }
+    }
+
+    #[test]
+    fn size_class_for_extreme_layouts() {
+        let max_align = 1 << (usize::BITS - 2);
+        let cases = [
+            (4096, 4096, Some(SlabSize::Slab4096Bytes)),
+            (8, 8192, None),
+            (0, max_align, None),
+            (4096, max_align, None),
+            (isize::MAX as usize, 1, None),
+            (isize::MAX as usize - 4095, 4096, None),
+        ];
+
+        for (size, align, expected) in cases {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            assert_eq!(SlabAllocator::size_class_for(&layout), expected);
+            assert_eq!(
+                SlabAllocator::waste_for(&layout),
+                expected.map_or(0, |class| class as usize - size)
+            );
         }
     }
 }
@@ -0,0 +1,188 @@
+//! Lock protecting the allocator.
+//! `spin::Mutex` is used by default.
+//! With `single_threaded` feature, a lock without atomic instructions is used instead.
+//! With `critical-section` feature, the lock enters a critical section provided by `critical_section` crate.
+
+#[cfg(all(
+    not(feature = "critical-section"),
+    not(feature = "single_threaded"),
+    feature = "spin"
+))]
+pub use spin::Mutex;
+
+#[cfg(all(not(feature = "critical-section"), feature = "single_threaded"))]
+pub use single_threaded::Mutex;
+
+#[cfg(feature = "critical-section")]
+pub use critical_section_lock::Mutex;
+
+#[cfg(all(
+    not(feature = "critical-section"),
+    not(feature = "single_threaded"),
+    not(feature = "spin")
+))]
+compile_error!("One of `spin`, `single_threaded` or `critical-section` feature must be enabled");
+
+#[cfg(all(not(feature = "critical-section"), feature = "single_threaded"))]
+mod single_threaded {
+    use core::cell::{Cell, UnsafeCell};
+    use core::ops::{Deref, DerefMut};
+
+    /// Lock for single core systems without atomic instructions.
+    ///
+    /// # Safety
+    /// It is only sound if the value is never accessed from more than one core
+    /// and never locked from an interrupt handler while it is locked.
+    /// Locking it again while it is locked panics instead of creating aliasing references.
+    pub struct Mutex<T> {
+        locked: Cell<bool>,
+        value: UnsafeCell<T>,
+    }
+
+    // Safety: the user of `single_threaded` feature guarantees that only one core exists.
+    unsafe impl<T: Send> Sync for Mutex<T> {}
+
+    impl<T> Mutex<T> {
+        /// Create a new lock.
+        pub const fn new(value: T) -> Self {
+            Mutex {
+                locked: Cell::new(false),
+                value: UnsafeCell::new(value),
+            }
+        }
+
+        /// Lock and return the guard.
+        ///
+        /// # Panics
+        /// If it is already locked, this function will panic.
+        pub fn lock(&self) -> MutexGuard<'_, T> {
+            assert!(!self.locked.replace(true), "The lock is already locked");
+            MutexGuard { mutex: self }
+        }
+    }
+
+    /// Guard that unlocks the `Mutex` when it is dropped.
+    pub struct MutexGuard<'a, T> {
+        mutex: &'a Mutex<T>,
+    }
+
+    impl<T> Deref for MutexGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            // Safety: the guard has exclusive access while the lock is locked.
+            unsafe { &*self.mutex.value.get() }
+        }
+    }
+
+    impl<T> DerefMut for MutexGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            // Safety: the guard has exclusive access while the lock is locked.
+            unsafe { &mut *self.mutex.value.get() }
+        }
+    }
+
+    impl<T> Drop for MutexGuard<'_, T> {
+        fn drop(&mut self) {
+            self.mutex.locked.set(false);
+        }
+    }
+}
+
+#[cfg(feature = "critical-section")]
+mod critical_section_lock {
+    use core::cell::{RefCell, RefMut};
+    use core::mem::ManuallyDrop;
+    use core::ops::{Deref, DerefMut};
+    use critical_section::{CriticalSection, RestoreState};
+
+    /// Lock that holds a critical section while it is locked.
+    /// Interrupts are masked (or other cores are excluded) by the `critical_section` implementation
+    /// instead of spinning.
+    pub struct Mutex<T> {
+        inner: critical_section::Mutex<RefCell<T>>,
+    }
+
+    impl<T> Mutex<T> {
+        /// Create a new lock.
+        pub const fn new(value: T) -> Self {
+            Mutex {
+                inner: critical_section::Mutex::new(RefCell::new(value)),
+            }
+        }
+
+        /// Enter a critical section and return the guard.
+        ///
+        /// # Panics
+        /// If it is already locked in the same critical section, this function will panic.
+        pub fn lock(&self) -> MutexGuard<'_, T> {
+            // Safety: the critical section is released by `MutexGuard::drop`
+            // after the borrow created in it is dropped.
+            let restore_state = unsafe { critical_section::acquire() };
+            let cs = unsafe { CriticalSection::new() };
+            let value = self
+                .inner
+                .borrow(cs)
+                .try_borrow_mut()
+                .expect("The lock is already locked");
+
+            MutexGuard {
+                value: ManuallyDrop::new(value),
+                restore_state,
+            }
+        }
+    }
+
+    /// Guard that leaves the critical section when it is dropped.
+    pub struct MutexGuard<'a, T> {
+        value: ManuallyDrop<RefMut<'a, T>>,
+        restore_state: RestoreState,
+    }
+
+    impl<T> Deref for MutexGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.value
+        }
+    }
+
+    impl<T> DerefMut for MutexGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            &mut self.value
+        }
+    }
+
+    impl<T> Drop for MutexGuard<'_, T> {
+        fn drop(&mut self) {
+            // Safety: the borrow is dropped before leaving the critical section,
+            // and `restore_state` was returned by the matching `acquire`.
+            unsafe {
+                ManuallyDrop::drop(&mut self.value);
+                critical_section::release(self.restore_state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(any(feature = "single_threaded", feature = "critical-section"))]
+mod lock_tests {
+    use super::Mutex;
+
+    #[test]
+    fn lock_and_unlock() {
+        let mutex = Mutex::new(0);
+        *mutex.lock() += 1;
+        *mutex.lock() += 1;
+        assert_eq!(*mutex.lock(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "The lock is already locked")]
+    fn reentrant_lock() {
+        let mutex = Mutex::new(0);
+        let _guard = mutex.lock();
+        let _ = mutex.lock();
+    }
+}
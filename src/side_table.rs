@@ -0,0 +1,70 @@
+//! Fixed-capacity table of live allocations shared by `track_caller` and `alloc-tags` features.
+
+/// Number of live allocations that can be recorded.
+const CAPACITY: usize = 256;
+
+/// Table of live allocations keyed by address with a value of each.
+/// It can't allocate, so allocations over the capacity are only counted.
+pub struct SideTable<T> {
+    entries: [Option<(usize, T)>; CAPACITY],
+    /// Number of allocations that couldn't be recorded.
+    overflow: usize,
+}
+
+impl<T> SideTable<T> {
+    /// Return an empty table.
+    pub const fn new() -> Self {
+        SideTable {
+            entries: [const { None }; CAPACITY],
+            overflow: 0,
+        }
+    }
+
+    /// Record `value` of the allocation at `ptr`.
+    /// Return false and count it as overflow if the table is full.
+    pub fn insert(&mut self, ptr: *mut u8, value: T) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.is_none()) {
+            Some(entry) => {
+                *entry = Some((ptr as usize, value));
+                true
+            }
+            None => {
+                self.overflow += 1;
+                false
+            }
+        }
+    }
+
+    /// Forget `ptr` and return its value.
+    /// Return `None` if it wasn't recorded (e.g. it overflowed).
+    pub fn remove(&mut self, ptr: *mut u8) -> Option<T> {
+        self.entries
+            .iter_mut()
+            .find(|entry| matches!(entry, Some((addr, _)) if *addr == ptr as usize))
+            .and_then(Option::take)
+            .map(|(_, value)| value)
+    }
+
+    /// Return the value of `ptr` to update it.
+    pub fn get_mut(&mut self, ptr: *mut u8) -> Option<&mut T> {
+        self.entries
+            .iter_mut()
+            .flatten()
+            .find(|(addr, _)| *addr == ptr as usize)
+            .map(|(_, value)| value)
+    }
+
+    /// Iterate over the address and the value of each recorded allocation.
+    #[cfg(feature = "track_caller")]
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.entries
+            .iter()
+            .flatten()
+            .map(|(addr, value)| (*addr, value))
+    }
+
+    /// Return the number of allocations that couldn't be recorded.
+    pub fn overflow(&self) -> usize {
+        self.overflow
+    }
+}
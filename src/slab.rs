@@ -1,20 +1,125 @@
+use core::ptr::NonNull;
+
 /// An enum that indicate slab object size
-#[derive(Copy, Clone)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum SlabSize {
+    Slab8Bytes = 8,
+    Slab16Bytes = 16,
+    Slab32Bytes = 32,
     Slab64Bytes = 64,
+    Slab96Bytes = 96,
     Slab128Bytes = 128,
+    Slab192Bytes = 192,
     Slab256Bytes = 256,
+    Slab384Bytes = 384,
     Slab512Bytes = 512,
+    Slab768Bytes = 768,
     Slab1024Bytes = 1024,
+    Slab1536Bytes = 1536,
     Slab2048Bytes = 2048,
     Slab4096Bytes = 4096,
 }
 
+impl SlabSize {
+    /// All slab sizes in ascending order.
+    pub const ALL: [SlabSize; 15] = [
+        SlabSize::Slab8Bytes,
+        SlabSize::Slab16Bytes,
+        SlabSize::Slab32Bytes,
+        SlabSize::Slab64Bytes,
+        SlabSize::Slab96Bytes,
+        SlabSize::Slab128Bytes,
+        SlabSize::Slab192Bytes,
+        SlabSize::Slab256Bytes,
+        SlabSize::Slab384Bytes,
+        SlabSize::Slab512Bytes,
+        SlabSize::Slab768Bytes,
+        SlabSize::Slab1024Bytes,
+        SlabSize::Slab1536Bytes,
+        SlabSize::Slab2048Bytes,
+        SlabSize::Slab4096Bytes,
+    ];
+
+    /// Return the index in `SlabSize::ALL` of the smallest slab size that is equal or greater than `size`.
+    /// Return `None` if `size` is over 4096 bytes.
+    pub(crate) const fn index_of(size: usize) -> Option<usize> {
+        if size > SlabSize::Slab4096Bytes as usize {
+            return None;
+        }
+
+        let order = size.next_power_of_two().trailing_zeros() as usize;
+        let index = INDEX_OF_ORDER[order];
+        // There is at most one size between `1 << (order - 1)` and `1 << order`.
+        if index > 0 && size <= SlabSize::ALL[index - 1] as usize {
+            Some(index - 1)
+        } else {
+            Some(index)
+        }
+    }
+
+    /// Return the slab size that is exactly `size` bytes.
+    /// Return `None` if `size` isn't a slab size. (`TryFrom<usize>` rounds it up instead.)
+    #[must_use]
+    pub fn from_exact(size: usize) -> Option<Self> {
+        SlabSize::ALL
+            .into_iter()
+            .find(|class| *class as usize == size)
+    }
+
+    /// Return the alignment that every object of this size is guaranteed to have.
+    /// Objects are placed at multiples of their size from a page aligned address,
+    /// so it is the largest power of two dividing the size.
+    #[must_use]
+    pub const fn align(self) -> usize {
+        1 << (self as usize).trailing_zeros()
+    }
+}
+
+/// Order of the largest slab size. (4096 == 1 << 12)
+const MAX_ORDER: usize = 12;
+
+/// Index in `SlabSize::ALL` of the smallest slab size that is equal or greater than `1 << order`.
+const INDEX_OF_ORDER: [usize; MAX_ORDER + 1] = {
+    let mut table = [0; MAX_ORDER + 1];
+    let mut order = 0;
+    while order <= MAX_ORDER {
+        let mut index = 0;
+        while (SlabSize::ALL[index] as usize) < 1 << order {
+            index += 1;
+        }
+        table[order] = index;
+        order += 1;
+    }
+    table
+};
+
+impl TryFrom<usize> for SlabSize {
+    type Error = ();
+
+    /// Round `size` up to the nearest slab size.
+    /// Return `Err` if `size` is over 4096 bytes.
+    fn try_from(size: usize) -> Result<Self, Self::Error> {
+        match Self::index_of(size) {
+            Some(index) => Ok(Self::ALL[index]),
+            None => Err(()),
+        }
+    }
+}
+
+impl From<SlabSize> for usize {
+    /// Return the size in bytes.
+    fn from(size: SlabSize) -> Self {
+        size as usize
+    }
+}
+
 /// Type of Slab
 /// * Full - all objects are allocated.
 /// * Partial - some objects are allocated.
 /// * Empty - no objects are allocated.
-enum SlabKind {
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlabKind {
     Full,
     Partial,
     Empty,
@@ -22,23 +127,21 @@ enum SlabKind {
 
 /// A linked list managing free objects.
 /// This struct is placed unused heap space.
+/// It is linked by raw pointers because the same memory is handed out as objects,
+/// and it is only dereferenced while it is in a free list.
 struct FreeObject {
-    next: Option<&'static mut Self>,
+    next: Option<NonNull<Self>>,
 }
 
-impl FreeObject {
-    /// Return address itself.
-    fn addr(&self) -> usize {
-        self as *const _ as usize
-    }
-}
+// A free object must fit in the smallest slab size.
+const _: () = assert!(core::mem::size_of::<FreeObject>() <= SlabSize::Slab8Bytes as usize);
 
 /// Slab header.
 struct SlabHead {
     len: usize,
     _kind: SlabKind,
-    head: Option<&'static mut FreeObject>,
-    _next: Option<&'static mut Self>,
+    head: Option<NonNull<FreeObject>>,
+    _next: Option<NonNull<Self>>,
 }
 
 impl SlabHead {
@@ -47,7 +150,7 @@ impl SlabHead {
         let mut new_list = Self::new_empty(SlabKind::Empty);
         for off in (0..num_of_object).rev() {
             let new_object = (start_addr + off * object_size as usize) as *mut FreeObject;
-            new_list.push(&mut *new_object);
+            new_list.push(NonNull::new_unchecked(new_object));
         }
 
         new_list
@@ -64,19 +167,51 @@ impl SlabHead {
     }
 
     /// Push new free object.
-    fn push(&mut self, slab: &'static mut FreeObject) {
-        slab.next = self.head.take();
+    /// # Safety
+    /// `object` must be valid for writes and not in any free list.
+    unsafe fn push(&mut self, object: NonNull<FreeObject>) {
+        object.as_ptr().write(FreeObject {
+            next: self.head.take(),
+        });
         self.len += 1;
-        self.head = Some(slab);
+        self.head = Some(object);
+    }
+
+    /// Remove free objects for which `f` returns true while keeping the order of the rest.
+    /// Return the number of removed objects.
+    fn remove_if(&mut self, mut f: impl FnMut(usize) -> bool) -> usize {
+        let mut reversed = Self::new_empty(self._kind);
+        while let Some(object) = self.pop() {
+            // Safety: it has just been popped from this list.
+            unsafe { reversed.push(object) };
+        }
+
+        let mut removed = 0;
+        while let Some(object) = reversed.pop() {
+            if f(object.as_ptr() as usize) {
+                removed += 1;
+            } else {
+                // Safety: it has just been popped from `reversed`.
+                unsafe { self.push(object) };
+            }
+        }
+        removed
+    }
+
+    /// Iterate over free objects without modifying the list.
+    fn free_objects(&self) -> impl Iterator<Item = *const FreeObject> + '_ {
+        // Safety: objects in the list are valid until they are popped.
+        core::iter::successors(self.head, |object| unsafe { object.as_ptr().read().next })
+            .map(|object| object.as_ptr().cast_const())
     }
 
     /// Pop free object.
-    fn pop(&mut self) -> Option<&'static mut FreeObject> {
-        self.head.take().map(|node| {
-            self.head = node.next.take();
-            self.len -= 1;
-            node
-        })
+    fn pop(&mut self) -> Option<NonNull<FreeObject>> {
+        let object = self.head?;
+        // Safety: objects in the list are valid until they are popped.
+        self.head = unsafe { object.as_ptr().read().next };
+        self.len -= 1;
+        Some(object)
     }
 }
 
@@ -85,6 +220,8 @@ impl SlabHead {
 /// Allocator normally use partial, but it use empty list and move one to partial when partial is empty.
 /// Note that only "empty" is used temporarily now. (TODO!)
 struct SlabFreeList {
+    /// Number of objects managed by these lists.
+    num_of_object: usize,
     _full: SlabHead,
     partial: SlabHead,
     empty: SlabHead,
@@ -97,35 +234,74 @@ impl SlabFreeList {
         assert!(num_of_object > 0);
 
         SlabFreeList {
+            num_of_object,
             _full: SlabHead::new_empty(SlabKind::Full),
             partial: SlabHead::new_empty(SlabKind::Partial),
             empty: SlabHead::new(start_addr, object_size, num_of_object),
         }
     }
 
+    /// Return the number of free objects.
+    fn free_count(&self) -> usize {
+        self.partial.len + self.empty.len
+    }
+
     /// Get free object from partial
-    fn pop_from_partial(&mut self) -> Option<&'static mut FreeObject> {
+    fn pop_from_partial(&mut self) -> Option<NonNull<FreeObject>> {
         self.partial.pop()
     }
 
     /// Get free object from empty
-    fn pop_from_empty(&mut self) -> Option<&'static mut FreeObject> {
+    fn pop_from_empty(&mut self) -> Option<NonNull<FreeObject>> {
         self.empty.pop()
     }
 }
 
+/// Information of a slab returned by `SlabAllocator::iter_all_slabs`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SlabInfo {
+    /// Start address of the region.
+    pub start_addr: usize,
+    /// Size of the region.
+    pub size: usize,
+    /// Whether the region is full, partially used or empty.
+    pub kind: SlabKind,
+    /// Size class of the objects in the region.
+    pub object_size: SlabSize,
+    /// Number of objects in the region.
+    pub num_of_objects: usize,
+    /// Number of objects currently allocated.
+    pub used_objects: usize,
+}
+
 /// Data unit of each slab size.
 pub struct SlabCache {
     /// Size of object. (e.g. 64byte, 128byte)
-    _object_size: SlabSize,
+    object_size: SlabSize,
+    /// Start address of the region.
+    start_addr: usize,
+    /// Size of the region.
+    alloc_size: usize,
     slab_free_list: SlabFreeList,
 }
 
+// Safety: the cache exclusively owns its region, and the free lists are only reached through it.
+unsafe impl Send for SlabCache {}
+
 impl SlabCache {
     /// Create new slab cache.
+    /// `start_addr` must be page aligned so that every object is aligned to its size class,
+    /// and it is checked in debug builds.
     pub unsafe fn new(start_addr: usize, alloc_size: usize, object_size: SlabSize) -> Self {
+        debug_assert!(
+            crate::align::is_aligned(start_addr, crate::constants::PAGE_SIZE),
+            "Region of slab cache must be page aligned"
+        );
+
         SlabCache {
-            _object_size: object_size,
+            object_size,
+            start_addr,
+            alloc_size,
             slab_free_list: SlabFreeList::new(start_addr, alloc_size, object_size),
         }
     }
@@ -133,19 +309,346 @@ impl SlabCache {
     /// Return object address according to `layout.size`.
     pub fn allocate(&mut self) -> *mut u8 {
         match self.slab_free_list.pop_from_partial() {
-            Some(object) => object.addr() as *mut u8,
+            Some(object) => object.as_ptr().cast::<u8>(),
             None => match self.slab_free_list.pop_from_empty() {
-                Some(object) => object.addr() as *mut u8,
+                Some(object) => {
+                    #[cfg(feature = "defmt")]
+                    defmt::trace!(
+                        "slab {=usize}: take an object from empty list, {=usize} free objects left",
+                        self.object_size as usize,
+                        self.slab_free_list.empty.len
+                    );
+                    object.as_ptr().cast::<u8>()
+                }
                 None => core::ptr::null_mut(),
             },
         }
     }
 
+    /// Return object address that the whole `object_size` bytes are zeroed.
+    pub fn allocate_zeroed(&mut self) -> *mut u8 {
+        let ptr = self.allocate();
+        if !ptr.is_null() {
+            unsafe { ptr.write_bytes(0, self.object_size as usize) };
+        }
+        ptr
+    }
+
+    /// Allocate up to `n` objects into `out` and return how many were allocated.
+    pub fn allocate_batch(&mut self, n: usize, out: &mut [*mut u8]) -> usize {
+        let mut count = 0;
+        for slot in out.iter_mut().take(n) {
+            let ptr = self.allocate();
+            if ptr.is_null() {
+                break;
+            }
+            *slot = ptr;
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Iterate over free objects of this cache.
+    fn free_objects(&self) -> impl Iterator<Item = *const FreeObject> + '_ {
+        self.slab_free_list
+            .partial
+            .free_objects()
+            .chain(self.slab_free_list.empty.free_objects())
+    }
+
+    /// Call `f` with each allocated object of this cache in ascending order of address.
+    /// Free objects are marked in a bitmap of 4096 objects on the stack,
+    /// so the free lists are walked once per 4096 objects instead of once per object.
+    pub fn for_each_live(&self, mut f: impl FnMut(*const u8)) {
+        const WINDOW: usize = 4096;
+        let object_size = self.object_size as usize;
+        let num_of_object = self.slab_free_list.num_of_object;
+
+        for window_start in (0..num_of_object).step_by(WINDOW) {
+            let window = window_start..num_of_object.min(window_start + WINDOW);
+            let mut free = [0_u64; WINDOW / u64::BITS as usize];
+            for object in self.free_objects() {
+                let index = (object as usize - self.start_addr) / object_size;
+                if window.contains(&index) {
+                    let bit = index - window_start;
+                    free[bit / 64] |= 1 << (bit % 64);
+                }
+            }
+
+            for index in window {
+                let bit = index - window_start;
+                if free[bit / 64] & (1 << (bit % 64)) == 0 {
+                    f((self.start_addr + index * object_size) as *const u8);
+                }
+            }
+        }
+    }
+
+    /// Return the number of free objects.
+    pub fn free_count(&self) -> usize {
+        self.slab_free_list.free_count()
+    }
+
+    /// Return the information of this cache without modifying the free lists.
+    pub fn info(&self) -> SlabInfo {
+        let num_of_objects = self.slab_free_list.num_of_object;
+        let used_objects = num_of_objects - self.slab_free_list.free_count();
+        let kind = match used_objects {
+            0 => SlabKind::Empty,
+            used if used == num_of_objects => SlabKind::Full,
+            _ => SlabKind::Partial,
+        };
+
+        SlabInfo {
+            start_addr: self.start_addr,
+            size: self.alloc_size,
+            kind,
+            object_size: self.object_size,
+            num_of_objects,
+            used_objects,
+        }
+    }
+
     /// Free object according to `layout.size`.
     pub fn deallocate(&mut self, ptr: *mut u8) {
         let ptr = ptr.cast::<FreeObject>();
         unsafe {
-            self.slab_free_list.empty.push(&mut *ptr);
+            self.slab_free_list.empty.push(NonNull::new_unchecked(ptr));
+        }
+    }
+
+    /// Iterate over addresses of objects that overlap `range`.
+    fn objects_in(&self, range: core::ops::Range<usize>) -> impl Iterator<Item = usize> {
+        let object_size = self.object_size as usize;
+        let start_addr = self.start_addr;
+        (0..self.slab_free_list.num_of_object)
+            .map(move |index| start_addr + index * object_size)
+            .filter(move |&object| object < range.end && range.start < object + object_size)
+    }
+
+    /// Return true if no object that overlaps `range` is allocated.
+    pub fn is_free_range(&self, range: core::ops::Range<usize>) -> bool {
+        let object_size = self.object_size as usize;
+        let free = self
+            .free_objects()
+            .filter(|&object| {
+                let object = object as usize;
+                object < range.end && range.start < object + object_size
+            })
+            .count();
+        free == self.objects_in(range).count()
+    }
+
+    /// Remove free objects that overlap `range` from the free lists so that they are never allocated.
+    /// Return the number of claimed objects.
+    pub fn claim(&mut self, range: core::ops::Range<usize>) -> usize {
+        let object_size = self.object_size as usize;
+        let overlaps = |object: usize| object < range.end && range.start < object + object_size;
+        self.slab_free_list.partial.remove_if(overlaps)
+            + self.slab_free_list.empty.remove_if(overlaps)
+    }
+
+    /// Return objects that overlap `range` to the free list.
+    /// Return the number of released objects.
+    /// # Safety
+    /// All of the objects must be claimed by `claim`.
+    pub unsafe fn release(&mut self, range: core::ops::Range<usize>) -> usize {
+        let mut released = 0;
+        for object in self.objects_in(range) {
+            self.slab_free_list
+                .empty
+                .push(NonNull::new_unchecked(object as *mut FreeObject));
+            released += 1;
+        }
+        released
+    }
+}
+
+#[cfg(test)]
+mod slab_tests {
+    use super::{SlabCache, SlabSize};
+
+    #[test]
+    fn slab_size_round_trip() {
+        for class in SlabSize::ALL {
+            let size = usize::from(class);
+            assert_eq!(SlabSize::from_exact(size), Some(class));
+            assert_eq!(SlabSize::try_from(size), Ok(class));
+        }
+
+        for size in [0, 1, 9, 63, 65, 100, 3000, 4095, 4097, 8192] {
+            assert_eq!(SlabSize::from_exact(size), None);
+        }
+    }
+
+    const REGION_SIZE: usize = 4096;
+    #[cfg_attr(not(feature = "page_size_16k"), repr(align(4096)))]
+    #[cfg_attr(feature = "page_size_16k", repr(align(16384)))]
+    struct DummyRegion {
+        space: [u8; REGION_SIZE],
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Region of slab cache must be page aligned")]
+    fn unaligned_region() {
+        let region = DummyRegion {
+            space: [0_u8; REGION_SIZE],
+        };
+
+        unsafe {
+            SlabCache::new(
+                &region.space as *const u8 as usize + 64,
+                REGION_SIZE - 64,
+                SlabSize::Slab64Bytes,
+            );
+        }
+    }
+
+    #[test]
+    fn reuse_objects_across_caches() {
+        let regions = [0; 2].map(|_| DummyRegion {
+            space: [0_u8; REGION_SIZE],
+        });
+
+        unsafe {
+            let mut caches = [
+                SlabCache::new(
+                    &regions[0].space as *const u8 as usize,
+                    REGION_SIZE,
+                    SlabSize::Slab64Bytes,
+                ),
+                SlabCache::new(
+                    &regions[1].space as *const u8 as usize,
+                    REGION_SIZE,
+                    SlabSize::Slab512Bytes,
+                ),
+            ];
+
+            for _ in 0..3 {
+                let objects = [0, 1, 2, 3].map(|n| {
+                    let cache = &mut caches[n % 2];
+                    let ptr = cache.allocate();
+                    // objects are overwritten while they are allocated.
+                    ptr.write_bytes(0xff, 64);
+                    ptr
+                });
+                for (n, &ptr) in objects.iter().enumerate().rev() {
+                    caches[n % 2].deallocate(ptr);
+                }
+
+                // the last freed object is reused first.
+                let ptr = caches[0].allocate();
+                assert_eq!(ptr, objects[0]);
+                caches[0].deallocate(ptr);
+            }
+            assert_eq!(caches[0].free_count(), REGION_SIZE / 64);
+            assert_eq!(caches[1].free_count(), REGION_SIZE / 512);
+        }
+    }
+
+    #[test]
+    fn allocate_zeroed() {
+        let region = DummyRegion {
+            space: [0_u8; REGION_SIZE],
+        };
+        let start_addr = &region.space as *const u8 as usize;
+
+        unsafe {
+            let mut cache = SlabCache::new(start_addr, REGION_SIZE, SlabSize::Slab256Bytes);
+            let ptr = cache.allocate_zeroed();
+            ptr.write_bytes(0xff, 256);
+            cache.deallocate(ptr);
+
+            let ptr = cache.allocate_zeroed();
+            let contents = core::slice::from_raw_parts(ptr, 256);
+            assert!(contents.iter().all(|b| *b == 0));
+        }
+    }
+
+    #[test]
+    fn iterate_live_objects() {
+        let region = DummyRegion {
+            space: [0_u8; REGION_SIZE],
+        };
+        let start_addr = &region.space as *const u8 as usize;
+        let count_live = |cache: &SlabCache| {
+            let mut count = 0;
+            cache.for_each_live(|_| count += 1);
+            count
+        };
+
+        unsafe {
+            let mut cache = SlabCache::new(start_addr, REGION_SIZE, SlabSize::Slab256Bytes);
+            assert_eq!(count_live(&cache), 0);
+
+            let first = cache.allocate();
+            let second = cache.allocate();
+            let third = cache.allocate();
+            cache.deallocate(second);
+            let mut live = [first as *const u8, third as *const u8];
+            live.sort();
+            let mut visited = [core::ptr::null(); 2];
+            let mut count = 0;
+            cache.for_each_live(|object| {
+                visited[count] = object;
+                count += 1;
+            });
+            assert_eq!(visited, live);
+        }
+    }
+
+    #[test]
+    fn iterate_live_objects_over_windows() {
+        // 3 windows of 4096 objects of 8 bytes.
+        let region_size = 3 * 4096 * 8;
+        let region_layout = core::alloc::Layout::from_size_align(region_size, 16384).unwrap();
+
+        unsafe {
+            let region = alloc::alloc::alloc(region_layout);
+            let mut cache = SlabCache::new(region as usize, region_size, SlabSize::Slab8Bytes);
+            while !cache.allocate().is_null() {}
+            // free every third object.
+            for index in (0..3 * 4096).step_by(3) {
+                cache.deallocate(region.add(index * 8));
+            }
+
+            let mut count = 0;
+            let mut last = None;
+            cache.for_each_live(|object| {
+                let index = (object as usize - region as usize) / 8;
+                assert_ne!(index % 3, 0);
+                assert!(last < Some(object));
+                last = Some(object);
+                count += 1;
+            });
+            assert_eq!(count, 2 * 4096);
+
+            alloc::alloc::dealloc(region, region_layout);
+        }
+    }
+
+    #[test]
+    fn iterate_free_objects() {
+        let region = DummyRegion {
+            space: [0_u8; REGION_SIZE],
+        };
+        let start_addr = &region.space as *const u8 as usize;
+        let num_of_object = REGION_SIZE / SlabSize::Slab256Bytes as usize;
+
+        unsafe {
+            let mut cache = SlabCache::new(start_addr, REGION_SIZE, SlabSize::Slab256Bytes);
+            assert_eq!(cache.free_objects().count(), num_of_object);
+            assert!(cache
+                .free_objects()
+                .all(|object| (start_addr..start_addr + REGION_SIZE).contains(&(object as usize))));
+
+            let addr = cache.allocate();
+            assert_eq!(cache.free_objects().count(), num_of_object - 1);
+            assert!(cache
+                .free_objects()
+                .all(|object| object.cast::<u8>() != addr.cast_const()));
         }
     }
 }
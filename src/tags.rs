@@ -0,0 +1,85 @@
+//! Side table attributing live allocations to tags (e.g. subsystems).
+//! It is enabled by `alloc-tags` feature.
+
+use crate::side_table::SideTable;
+
+/// Number of tags. Valid tags are `0..NUM_OF_TAGS`.
+pub const NUM_OF_TAGS: usize = 8;
+
+/// Usage of the heap by a tag.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagStats {
+    /// Requested bytes of live allocations.
+    pub bytes: usize,
+    /// Number of live allocations.
+    pub count: usize,
+}
+
+/// Table of live allocations and their tags.
+/// Up to 256 allocations are attributed, and the rest are only counted.
+pub struct TagTable {
+    /// Tag given to following allocations.
+    current: u8,
+    stats: [TagStats; NUM_OF_TAGS],
+    /// Tag and requested size of each recorded allocation.
+    entries: SideTable<(u8, usize)>,
+}
+
+impl TagTable {
+    /// Return an empty table whose current tag is 0.
+    pub const fn new() -> Self {
+        TagTable {
+            current: 0,
+            stats: [TagStats { bytes: 0, count: 0 }; NUM_OF_TAGS],
+            entries: SideTable::new(),
+        }
+    }
+
+    /// Set the tag given to following allocations and return the previous one.
+    ///
+    /// # Panics
+    /// If `tag` isn't less than `NUM_OF_TAGS`, it will panic.
+    pub fn set_current(&mut self, tag: u8) -> u8 {
+        assert!((tag as usize) < NUM_OF_TAGS, "Tag must be less than 8");
+        core::mem::replace(&mut self.current, tag)
+    }
+
+    /// Attribute `size` bytes at `ptr` to the current tag.
+    pub fn insert(&mut self, ptr: *mut u8, size: usize) {
+        if self.entries.insert(ptr, (self.current, size)) {
+            let stats = &mut self.stats[self.current as usize];
+            stats.bytes += size;
+            stats.count += 1;
+        }
+    }
+
+    /// Change the size of `ptr` resized in place to `new_size` bytes.
+    /// Nothing happens if it wasn't recorded (e.g. it overflowed).
+    pub fn resize(&mut self, ptr: *mut u8, new_size: usize) {
+        if let Some((tag, size)) = self.entries.get_mut(ptr) {
+            let stats = &mut self.stats[*tag as usize];
+            stats.bytes = stats.bytes - *size + new_size;
+            *size = new_size;
+        }
+    }
+
+    /// Remove `ptr` from the tag recorded when it was allocated.
+    /// Nothing happens if it wasn't recorded (e.g. it overflowed).
+    pub fn remove(&mut self, ptr: *mut u8) {
+        if let Some((tag, size)) = self.entries.remove(ptr) {
+            let stats = &mut self.stats[tag as usize];
+            stats.bytes -= size;
+            stats.count -= 1;
+        }
+    }
+
+    /// Return the usage of each tag.
+    pub fn stats(&self) -> [TagStats; NUM_OF_TAGS] {
+        self.stats
+    }
+
+    /// Return the number of allocations that couldn't be recorded.
+    pub fn overflow(&self) -> usize {
+        self.entries.overflow()
+    }
+}
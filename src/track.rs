@@ -0,0 +1,61 @@
+//! Side table recording the call site of each live allocation.
+//! It is enabled by `track_caller` feature.
+//!
+//! The call site is passed down by `#[track_caller]`, which `GlobalAlloc` methods can't have.
+//! So allocations through `WildScreenAlloc` (e.g. by `Box` or `Vec`) are all recorded at the call
+//! in its `GlobalAlloc` implementation in src/lib.rs, and only direct calls of `SlabAllocator`
+//! methods record the caller.
+
+use crate::side_table::SideTable;
+use core::fmt;
+use core::panic::Location;
+
+/// Table of live allocations and their call sites.
+/// Up to 256 allocations are recorded, and the rest are only counted.
+pub struct CallerTable {
+    entries: SideTable<&'static Location<'static>>,
+}
+
+impl CallerTable {
+    /// Return an empty table.
+    pub const fn new() -> Self {
+        CallerTable {
+            entries: SideTable::new(),
+        }
+    }
+
+    /// Record that `ptr` was allocated at `location`.
+    pub fn insert(&mut self, ptr: *mut u8, location: &'static Location<'static>) {
+        self.entries.insert(ptr, location);
+    }
+
+    /// Record that `ptr` was resized in place at `location`.
+    /// Nothing happens if it wasn't recorded (e.g. it overflowed).
+    pub fn update(&mut self, ptr: *mut u8, location: &'static Location<'static>) {
+        if let Some(entry) = self.entries.get_mut(ptr) {
+            *entry = location;
+        }
+    }
+
+    /// Forget `ptr`.
+    /// Nothing happens if it wasn't recorded (e.g. it overflowed).
+    pub fn remove(&mut self, ptr: *mut u8) {
+        self.entries.remove(ptr);
+    }
+
+    /// Write each recorded allocation and its call site to `w`.
+    pub fn dump(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        for (addr, location) in self.entries.iter() {
+            writeln!(w, "{addr:#x} allocated at {location}")?;
+        }
+        if self.entries.overflow() > 0 {
+            writeln!(
+                w,
+                "{} allocations were not recorded",
+                self.entries.overflow()
+            )?;
+        }
+
+        Ok(())
+    }
+}
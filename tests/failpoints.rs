@@ -0,0 +1,70 @@
+//! Check that `Vec::try_reserve` sees the failures injected by the `failpoints` feature.
+//! It needs `WildScreenAlloc` as the global allocator, so it runs without the test harness
+//! and the system allocator serves everything outside the heap.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use wild_screen_alloc::WildScreenAlloc;
+
+const HEAP_SIZE: usize = 32 * 16384;
+const HEAP_ALIGN: usize = 16384;
+
+static ALLOCATOR: WildScreenAlloc = WildScreenAlloc::empty();
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static HEAP_START: AtomicUsize = AtomicUsize::new(0);
+
+struct Global;
+
+unsafe impl GlobalAlloc for Global {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if ACTIVE.load(Ordering::Relaxed) {
+            ALLOCATOR.alloc(layout)
+        } else {
+            System.alloc(layout)
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let start = HEAP_START.load(Ordering::Relaxed);
+        if (start..start + HEAP_SIZE).contains(&(ptr as usize)) {
+            ALLOCATOR.dealloc(ptr, layout)
+        } else {
+            System.dealloc(ptr, layout)
+        }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Global = Global;
+
+fn main() {
+    let heap = unsafe { System.alloc(Layout::from_size_align(HEAP_SIZE, HEAP_ALIGN).unwrap()) };
+    assert!(!heap.is_null());
+    HEAP_START.store(heap as usize, Ordering::Relaxed);
+    unsafe {
+        ALLOCATOR.init(heap as usize, HEAP_SIZE);
+    }
+    ACTIVE.store(true, Ordering::Relaxed);
+
+    let mut small: Vec<u8> = Vec::new();
+    let mut large: Vec<u8> = Vec::new();
+    ALLOCATOR.fail_next_allocations(2);
+    let small_failed = small.try_reserve(100).is_err();
+    let large_failed = large.try_reserve(16 * 1024).is_err();
+    let used_while_failing = ALLOCATOR.used();
+
+    ALLOCATOR.fail_next_allocations(0);
+    let small_reserved = small.try_reserve(100).is_ok();
+    let large_reserved = large.try_reserve(16 * 1024).is_ok();
+    let used_after = ALLOCATOR.used();
+    ACTIVE.store(false, Ordering::Relaxed);
+
+    assert!(small_failed && large_failed);
+    assert_eq!(used_while_failing, 0);
+    assert!(small_reserved && large_reserved);
+    assert!(used_after >= 100 + 16 * 1024);
+    drop(small);
+    drop(large);
+    assert_eq!(ALLOCATOR.used(), 0);
+    println!("failpoints: ok");
+}